@@ -1,84 +1,195 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+use itertools::Itertools;
+
+use crate::matcher::{Match, TokenMatcher};
 use crate::problem::Problem;
 
 pub struct DayOne;
 
 impl Problem for DayOne {
     fn part_one(&self, input: &str) -> String {
-        let first_digit = input
-            .lines()
-            .flat_map(|s| s.
-                chars()
-                .find(|c| c.
-                    is_digit(10))
-            )
-            .collect::<Vec<char>>();
-
-        let last_digit = input
-            .lines()
-            .flat_map(|s| s.
-                chars()
-                .rev()
-                .find(|c| c.
-                    is_digit(10))
-            )
-            .collect::<Vec<char>>();
-
-        let sum = first_digit
-            .iter()
-            .zip(last_digit.iter())
-            .into_iter()
-            .map(|(&x, &y)| {
-                (x.to_string() + &y.to_string()).parse::<u32>().unwrap()
-            })
-            .sum::<u32>();
-        sum.to_string()
+        Calibration::with_words(&[])
+            .total(input, MissingDigits::Fail)
+            .unwrap_or_else(|e| panic!("{e}"))
+            .to_string()
     }
 
     fn part_two(&self, input: &str) -> String {
+        Calibration::with_words(ENGLISH)
+            .total(input, MissingDigits::Fail)
+            .unwrap_or_else(|e| panic!("{e}"))
+            .to_string()
+    }
+}
+
+/// The digits one to nine spelled out in English.
+pub(crate) const ENGLISH: &[(&str, u32)] = &[
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+];
+
+/// Reads calibration values by finding the first and last digit token in a
+/// line. Numerals always count; any extra words come from a dictionary.
+pub(crate) struct Calibration {
+    matcher: TokenMatcher<u32>,
+}
+
+impl Calibration {
+    pub(crate) fn with_words(words: &[(&str, u32)]) -> Self {
+        let numerals = ('0'..='9').map(|digit| {
+            (digit.to_string(), digit.to_digit(10).expect("is a numeral"))
+        });
+        let words = words.iter().map(|(word, value)| (word.to_string(), *value));
+        Calibration { matcher: TokenMatcher::new(numerals.chain(words)) }
+    }
+
+    /// Every digit token in the line by position. Tokens may overlap, so
+    /// `"twone"` holds a two and a one; when two tokens start at the same byte
+    /// only the longer one counts.
+    pub(crate) fn digits(&self, line: &str) -> Vec<Digit> {
+        let mut found = self.matcher.find_iter(line).collect::<Vec<Match<u32>>>();
+        found.sort_by_key(|found| (found.start, Reverse(found.token.len())));
+        found.dedup_by_key(|found| found.start);
+        found
+            .into_iter()
+            .map(|found| Digit { value: *found.value, position: found.start })
+            .collect()
+    }
+
+    /// Calibrates the line with the given 1-based number: its first digit
+    /// followed by its last one. Tokens worth more than nine contribute all of
+    /// their decimal digits, so `"ten"` on its own reads as 1010.
+    pub(crate) fn calibrate(&self, number: usize, line: &str) -> Result<LineCalibration, CalibrationError> {
+        let digits = self.digits(line);
+        let (first, last) = match (digits.first(), digits.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(CalibrationError::NoDigits { line: number }),
+        };
+        let value = format!("{}{}", first.value, last.value)
+            .parse()
+            .map_err(|_| CalibrationError::Overflow { line: number })?;
+        Ok(LineCalibration { line: number, digits, value })
+    }
+
+    /// Sum of every line's calibration value.
+    pub(crate) fn total(&self, input: &str, missing: MissingDigits) -> Result<u32, CalibrationError> {
         input
             .lines()
-            .map(|line| {
-                let mut it = (0..line.len()).filter_map(|index| {
-                    let reduced_line = &line[index..];
-                    let result = if reduced_line.starts_with("one") {
-                        '1'
-                    } else if reduced_line.starts_with("two") {
-                        '2'
-                    } else if reduced_line.starts_with("three") {
-                        '3'
-                    } else if reduced_line.starts_with("four") {
-                        '4'
-                    } else if reduced_line.starts_with("five") {
-                        '5'
-                    } else if reduced_line.starts_with("six") {
-                        '6'
-                    } else if reduced_line.starts_with("seven") {
-                        '7'
-                    } else if reduced_line.starts_with("eight") {
-                        '8'
-                    } else if reduced_line.starts_with("nine") {
-                        '9'
-                    } else {
-                        reduced_line.chars().next().unwrap()
-                    };
-                    result.to_digit(10)
-                });
-                let first = it.next().expect("Must be a number");
-
-                match it.last() {
-                    Some(num) => format!("{first}{num}"),
-                    None => format!("{first}{first}")
-                }.parse::<u32>().expect("Must be a number")
+            .enumerate()
+            .map(|(index, line)| match (self.calibrate(index + 1, line), missing) {
+                (Ok(calibration), _) => Ok(calibration.value),
+                (Err(CalibrationError::NoDigits { .. }), MissingDigits::Skip) => Ok(0),
+                (Err(e), _) => Err(e),
             })
-            .sum::<u32>()
-            .to_string()
+            .sum()
+    }
+}
+
+/// A digit token and the byte offset it starts at.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Digit {
+    pub(crate) value: u32,
+    pub(crate) position: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct LineCalibration {
+    pub(crate) line: usize,
+    pub(crate) digits: Vec<Digit>,
+    pub(crate) value: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CalibrationError {
+    NoDigits { line: usize },
+    Overflow { line: usize },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::NoDigits { line } => write!(f, "line {line} has no digits"),
+            CalibrationError::Overflow { line } => {
+                write!(f, "line {line} has a calibration value that doesn't fit in a u32")
+            }
+        }
     }
 }
 
+/// What [`Calibration::total`] does with a line that has no digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MissingDigits {
+    Fail,
+    /// Leave the line out of the sum.
+    Skip,
+}
+
+/// A table of every line's digits, their positions and calibration value.
+/// `None` when there is no such part.
+pub(crate) fn explain(input: &str, part: u8, missing: MissingDigits) -> Option<String> {
+    let calibration = match part {
+        1 => Calibration::with_words(&[]),
+        2 => Calibration::with_words(ENGLISH),
+        _ => return None,
+    };
+    Some(explain_with(&calibration, input, missing))
+}
+
+fn explain_with(calibration: &Calibration, input: &str, missing: MissingDigits) -> String {
+    let describe = |digits: &[Digit]| {
+        if digits.is_empty() {
+            return "none".to_string();
+        }
+        digits
+            .iter()
+            .map(|digit| format!("{}@{}", digit.value, digit.position))
+            .join(" ")
+    };
+
+    let mut lines = vec![format!("{:>5}  {:>5}  {:<24}  text", "line", "value", "digits")];
+    let mut total = 0;
+    let mut skipped = 0;
+    for (index, line) in input.lines().enumerate() {
+        match calibration.calibrate(index + 1, line) {
+            Ok(calibrated) => {
+                let digits = describe(&calibrated.digits);
+                total += calibrated.value;
+                lines.push(format!("{:>5}  {:>5}  {digits:<24}  {line}", calibrated.line, calibrated.value));
+            }
+            Err(e) => {
+                let digits = describe(&calibration.digits(line));
+                lines.push(format!("{:>5}  {:>5}  {digits:<24}  {line}", index + 1, "-"));
+                // Only a line without digits can be skipped, like in `total`
+                if missing == MissingDigits::Skip && matches!(e, CalibrationError::NoDigits { .. }) {
+                    skipped += 1;
+                    continue;
+                }
+                lines.push(format!("error: {e}"));
+                return lines.join("\n");
+            }
+        }
+    }
+    lines.push(format!("total: {total} ({skipped} lines skipped)"));
+    lines.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn value(calibration: &Calibration, line: &str) -> Option<u32> {
+        calibration.calibrate(1, line).ok().map(|calibrated| calibrated.value)
+    }
+
     #[test]
     fn _part_one() {
         let input = "1abc2
@@ -98,4 +209,83 @@ zoneight234
 7pqrstsixteen";
         assert_eq!(DayOne.part_two(input), "281");
     }
+
+    #[test]
+    fn overlapping_words() {
+        let calibration = Calibration::with_words(ENGLISH);
+        assert_eq!(value(&calibration, "eightwo"), Some(82));
+        assert_eq!(value(&calibration, "twone"), Some(21));
+        assert_eq!(value(&calibration, "oneight"), Some(18));
+        assert_eq!(value(&calibration, "sevenine"), Some(79));
+        assert_eq!(value(&calibration, "treb7uchet"), Some(77));
+        assert_eq!(value(&calibration, "abcdef"), None);
+    }
+
+    #[test]
+    fn custom_dictionaries() {
+        let german = [("eins", 1), ("zwei", 2), ("drei", 3), ("vier", 4), ("neun", 9)];
+        let calibration = Calibration::with_words(&german);
+        assert_eq!(value(&calibration, "xzweinsx"), Some(21));
+        assert_eq!(value(&calibration, "one3neun"), Some(39));
+
+        let calibration = Calibration::with_words(&[("zero", 0), ("one", 1), ("ten", 10)]);
+        assert_eq!(value(&calibration, "zerone"), Some(1));
+        assert_eq!(value(&calibration, "ten"), Some(1010));
+        assert_eq!(value(&calibration, "ten9"), Some(109));
+        assert_eq!(value(&calibration, "9ten"), Some(910));
+
+        let calibration = Calibration::with_words(&[("lots", 4_000_000_000)]);
+        assert_eq!(calibration.calibrate(7, "lots"), Err(CalibrationError::Overflow { line: 7 }));
+        assert_eq!(value(&Calibration::with_words(&[]), "one2three"), Some(22));
+    }
+
+    #[test]
+    fn missing_digits() {
+        let input = "1abc2
+pqr3stu8vwx
+nothing
+treb7uchet";
+        let calibration = Calibration::with_words(&[]);
+        assert_eq!(
+            calibration.total(input, MissingDigits::Fail),
+            Err(CalibrationError::NoDigits { line: 3 })
+        );
+        assert_eq!(calibration.total(input, MissingDigits::Skip), Ok(12 + 38 + 77));
+        assert_eq!(
+            calibration.calibrate(2, "pqr3stu8vwx"),
+            Ok(LineCalibration {
+                line: 2,
+                digits: vec![Digit { value: 3, position: 3 }, Digit { value: 8, position: 7 }],
+                value: 38,
+            })
+        );
+    }
+
+    #[test]
+    fn explain_table() {
+        let input = "two1nine
+nothing
+xtwone3four";
+        assert_eq!(
+            explain(input, 2, MissingDigits::Skip).unwrap(),
+            " line  value  digits                    text
+    1     29  2@0 1@3 9@4               two1nine
+    2      -  none                      nothing
+    3     24  2@1 1@3 3@6 4@7           xtwone3four
+total: 53 (1 lines skipped)"
+        );
+        assert!(explain(input, 2, MissingDigits::Fail).unwrap().ends_with("error: line 2 has no digits"));
+        assert_eq!(explain(input, 3, MissingDigits::Fail), None);
+
+        let calibration = Calibration::with_words(&[("lots", 4_000_000_000)]);
+        let input = "nothing\n1lots";
+        assert_eq!(calibration.total(input, MissingDigits::Skip), Err(CalibrationError::Overflow { line: 2 }));
+        assert_eq!(
+            explain_with(&calibration, input, MissingDigits::Skip),
+            " line  value  digits                    text
+    1      -  none                      nothing
+    2      -  1@0 4000000000@1          1lots
+error: line 2 has a calibration value that doesn't fit in a u32"
+        );
+    }
 }
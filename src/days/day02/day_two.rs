@@ -1,54 +1,219 @@
 use std::collections::BTreeMap;
-use std::ops::Not;
+use std::fmt;
 
 use nom::IResult;
 use nom::bytes::complete::tag;
 use nom::character::complete::{alpha1, line_ending};
 use nom::character::complete;
+use nom::combinator::all_consuming;
 use nom::multi::separated_list1;
 use nom::sequence::{preceded, separated_pair};
 
 use crate::problem::Problem;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Cube<'a> {
     color: &'a str,
     amount: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Game<'a> {
     id: u32,
     rounds: Vec<Vec<Cube<'a>>>,
 }
 
 impl<'a> Game<'a> {
-    fn valid(&self, map: &BTreeMap<&str, u32>) -> Option<u32> {
-        self.rounds
-            .iter()
-            .any(|round| {
-                round.iter().any(|cube| {
-                    cube.amount > *map.get(cube.color).expect("This is valid")
-                })
-            })
-            .not()
-            .then_some(self.id)
+    /// The fewest cubes of each colour that make every round possible. Each
+    /// of `colours` is included even if it is never drawn, needing none.
+    fn minimal_bag(&self, colours: &[&str]) -> Bag {
+        let mut bag = Bag::new(colours.iter().map(|colour| (*colour, 0)));
+        for cube in self.rounds.iter().flatten() {
+            let held = bag.cubes.entry(cube.color.to_string()).or_insert(0);
+            *held = (*held).max(cube.amount);
+        }
+        bag
     }
+}
 
-    fn power(&self) -> u32 {
-        let mut map = BTreeMap::from([
-            ("red", 0),
-            ("green", 0),
-            ("blue", 0)
-        ]);
-
-        for round in self.rounds.iter() {
-            for cube in round.iter() {
-                let _ = map.entry(cube.color).and_modify(|v| *v = (*v).max(cube.amount)).or_insert(cube.amount);
+/// How many cubes of each colour a bag holds. Colours the bag doesn't
+/// mention aren't in it at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Bag {
+    cubes: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BagError {
+    /// The bag description isn't a list like `12 red, 13 green`.
+    Malformed(String),
+    UnknownColour { game: u32, round: usize, colour: String },
+    TooMany { game: u32, round: usize, colour: String, drawn: u32, held: u32 },
+}
+
+impl fmt::Display for BagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BagError::Malformed(bag) => {
+                write!(f, "`{bag}` should list cubes like `12 red, 13 green, 14 blue`")
+            }
+            BagError::UnknownColour { game, round, colour } => {
+                write!(f, "game {game} round {round} draws {colour} cubes, which the bag doesn't have")
+            }
+            BagError::TooMany { game, round, colour, drawn, held } => write!(
+                f,
+                "game {game} round {round} draws {drawn} {colour} cubes, but the bag only holds {held}"
+            ),
+        }
+    }
+}
+
+impl Bag {
+    pub(crate) fn new<S: Into<String>>(cubes: impl IntoIterator<Item = (S, u32)>) -> Self {
+        Bag { cubes: cubes.into_iter().map(|(colour, amount)| (colour.into(), amount)).collect() }
+    }
+
+    /// The bag from the puzzle: 12 red, 13 green and 14 blue cubes.
+    pub(crate) fn standard() -> Self {
+        Bag::new([("red", 12), ("green", 13), ("blue", 14)])
+    }
+
+    /// Reads a bag written like a round, e.g. `12 red, 13 green, 14 blue`.
+    pub(crate) fn parse(bag: &str) -> Result<Self, BagError> {
+        let (_, cubes) = all_consuming(round)(bag.trim())
+            .map_err(|_| BagError::Malformed(bag.to_string()))?;
+        Ok(Bag::new(cubes.into_iter().map(|cube| (cube.color, cube.amount))))
+    }
+
+    fn colours(&self) -> Vec<&str> {
+        self.cubes.keys().map(String::as_str).collect()
+    }
+
+    /// Checks every round of the game against the bag, stopping at the first
+    /// cube that couldn't have been drawn from it.
+    fn check(&self, game: &Game) -> Result<(), BagError> {
+        for (index, round) in game.rounds.iter().enumerate() {
+            for cube in round {
+                let colour = cube.color.to_string();
+                match self.cubes.get(cube.color) {
+                    None => return Err(BagError::UnknownColour { game: game.id, round: index + 1, colour }),
+                    Some(&held) if cube.amount > held => {
+                        return Err(BagError::TooMany { game: game.id, round: index + 1, colour, drawn: cube.amount, held })
+                    }
+                    Some(_) => {}
+                }
             }
         }
-        map.values().product()
+        Ok(())
+    }
+
+    /// Whether the game could have been played with this bag. Cubes of a
+    /// colour the bag doesn't know about are an error rather than impossible.
+    fn possible(&self, game: &Game) -> Result<bool, BagError> {
+        match self.check(game) {
+            Ok(()) => Ok(true),
+            Err(BagError::TooMany { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
+
+    fn power(&self) -> u32 {
+        self.cubes.values().product()
+    }
+
+    /// The same bag with one more cube of `colour`, which may be new to it.
+    fn with_one_more(&self, colour: &str) -> Bag {
+        let mut bag = self.clone();
+        *bag.cubes.entry(colour.to_string()).or_insert(0) += 1;
+        bag
+    }
+}
+
+impl fmt::Display for Bag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cubes = self
+            .cubes
+            .iter()
+            .map(|(colour, amount)| format!("{amount} {colour}"))
+            .collect::<Vec<String>>();
+        write!(f, "{}", cubes.join(", "))
+    }
+}
+
+/// The largest number of cubes of each colour drawn in any round of any game.
+fn max_draws(games: &[Game]) -> Bag {
+    games.iter().fold(Bag::new::<&str>([]), |mut bag, game| {
+        for (colour, amount) in game.minimal_bag(&[]).cubes {
+            let held = bag.cubes.entry(colour).or_insert(0);
+            *held = (*held).max(amount);
+        }
+        bag
+    })
+}
+
+/// Ids of the games that are impossible with `bag` but would be possible with
+/// one more cube of `colour`.
+fn unlocked_by(games: &[Game], bag: &Bag, colour: &str) -> Vec<u32> {
+    let bigger = bag.with_one_more(colour);
+    games
+        .iter()
+        .filter(|game| bag.check(game).is_err() && bigger.check(game).is_ok())
+        .map(|game| game.id)
+        .collect()
+}
+
+/// How many games have each number of rounds.
+fn round_counts(games: &[Game]) -> BTreeMap<usize, usize> {
+    games.iter().fold(BTreeMap::new(), |mut counts, game| {
+        *counts.entry(game.rounds.len()).or_insert(0) += 1;
+        counts
+    })
+}
+
+/// A summary of the games: the biggest draws, how long games run and which
+/// extra cube would make the most difference to `bag`.
+pub(crate) fn stats(input: &str, bag: &Bag) -> String {
+    let (_, games) = parse_games(input).expect("This should not fail");
+    let max_draws = max_draws(&games);
+
+    let mut lines = vec![format!("max draws: {max_draws}"), "rounds per game:".to_string()];
+    for (rounds, count) in round_counts(&games) {
+        let plural = if count == 1 { "" } else { "s" };
+        lines.push(format!("  {rounds} rounds: {count} game{plural}"));
+    }
+    lines.push(format!("one more cube than {bag} would make possible:"));
+    let mut colours = bag.colours();
+    colours.extend(max_draws.colours());
+    colours.sort();
+    colours.dedup();
+    for colour in colours {
+        let unlocked = unlocked_by(&games, bag, colour);
+        let unlocked = if unlocked.is_empty() {
+            "no games".to_string()
+        } else {
+            format!("games {}", unlocked.iter().map(u32::to_string).collect::<Vec<String>>().join(", "))
+        };
+        lines.push(format!("  {colour}: {unlocked}"));
+    }
+    lines.join("\n")
+}
+
+/// Checks every game against the bag, listing why the impossible ones are.
+pub(crate) fn possible_games(input: &str, bag: &Bag) -> String {
+    let (_, games) = parse_games(input).expect("This should not fail");
+    let mut total = 0;
+    let mut lines = games
+        .iter()
+        .map(|game| match bag.check(game) {
+            Ok(()) => {
+                total += game.id;
+                format!("game {} is possible", game.id)
+            }
+            Err(e) => e.to_string(),
+        })
+        .collect::<Vec<String>>();
+    lines.push(format!("sum of possible game ids: {total}"));
+    lines.join("\n")
 }
 
 pub struct DayTwo;
@@ -84,32 +249,77 @@ fn parse_games(input: &str) -> IResult<&str, Vec<Game>> {
 
 impl Problem for DayTwo {
     fn part_one(&self, input: &str) -> String {
-        let map = BTreeMap::from([
-            ("red", 12),
-            ("green", 13),
-            ("blue", 14)
-        ]);
+        let bag = Bag::standard();
         let (_, games) = parse_games(input).expect("This should not fail");
-        games
-            .into_iter()
-            .filter_map(|game| game.valid(&map))
-            .sum::<u32>()
-            .to_string()
+        let total = games
+            .iter()
+            .map(|game| bag.possible(game).map(|possible| if possible { game.id } else { 0 }))
+            .sum::<Result<u32, BagError>>();
+        match total {
+            Ok(total) => total.to_string(),
+            Err(e) => format!("error: {e}"),
+        }
     }
 
     fn part_two(&self, input: &str) -> String {
+        let bag = Bag::standard();
+        let colours = bag.colours();
         let (_, games) = parse_games(input).expect("This should not fail");
         games
             .into_iter()
-            .map(|game| game.power())
+            .map(|game| game.minimal_bag(&colours).power())
             .sum::<u32>()
             .to_string()
     }
 }
 
+#[cfg(test)]
 mod tests {
-    use crate::days::day02::day_two::DayTwo;
+    use std::collections::BTreeMap;
+
+    use nom::combinator::all_consuming;
+
+    use crate::days::day02::day_two::{Bag, BagError, DayTwo, Game, cube, max_draws, parse_games, possible_games, round, round_counts, stats, unlocked_by};
     use crate::problem::Problem;
+    use crate::testing::{Rng, mutations, replace_one};
+
+    fn random_games(rng: &mut Rng) -> String {
+        (1..=rng.between(1, 6))
+            .map(|id| {
+                let rounds = (0..rng.between(1, 5))
+                    .map(|_| {
+                        (0..rng.between(1, 4))
+                            .map(|_| format!("{} {}", rng.below(30), rng.pick(&["red", "green", "blue", "teal"])))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    })
+                    .collect::<Vec<String>>();
+                format!("Game {id}: {}", rounds.join("; "))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn print_games(games: &[Game]) -> String {
+        games
+            .iter()
+            .map(|game| {
+                let rounds = game
+                    .rounds
+                    .iter()
+                    .map(|round| {
+                        round
+                            .iter()
+                            .map(|cube| format!("{} {}", cube.amount, cube.color))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    })
+                    .collect::<Vec<String>>();
+                format!("Game {}: {}", game.id, rounds.join("; "))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
 
     #[test]
     fn _part_one() {
@@ -121,6 +331,16 @@ Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
         assert_eq!(DayTwo.part_one(input), "8")
     }
 
+    #[test]
+    fn unknown_colours() {
+        let input = "Game 1: 3 blue, 4 red
+Game 2: 1 blue; 2 teal, 1 red";
+        assert_eq!(
+            DayTwo.part_one(input),
+            "error: game 2 round 2 draws teal cubes, which the bag doesn't have"
+        );
+    }
+
     #[test]
     fn _part_two() {
         let input = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
@@ -130,4 +350,127 @@ Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
 Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
         assert_eq!(DayTwo.part_two(input), "2286")
     }
+
+    #[test]
+    fn bag_violations() {
+        let input = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red";
+        let (_, games) = parse_games(input).unwrap();
+        let bag = Bag::standard();
+        assert_eq!(bag.check(&games[0]), Ok(()));
+        assert_eq!(
+            bag.check(&games[1]),
+            Err(BagError::TooMany { game: 3, round: 1, colour: "red".to_string(), drawn: 20, held: 12 })
+        );
+        assert_eq!(
+            bag.check(&games[2]),
+            Err(BagError::TooMany { game: 4, round: 3, colour: "blue".to_string(), drawn: 15, held: 14 })
+        );
+
+        let bag = Bag::parse("20 red, 6 blue").unwrap();
+        assert_eq!(
+            bag.check(&games[1]),
+            Err(BagError::UnknownColour { game: 3, round: 1, colour: "green".to_string() })
+        );
+        assert!(bag.possible(&games[1]).is_err());
+        assert_eq!(Bag::parse("12 red,").unwrap_err(), BagError::Malformed("12 red,".to_string()));
+
+        assert_eq!(
+            possible_games(input, &Bag::parse("14 red, 13 green, 15 blue").unwrap()),
+            "game 1 is possible
+game 3 round 1 draws 20 red cubes, but the bag only holds 14
+game 4 is possible
+sum of possible game ids: 5"
+        );
+    }
+
+    #[test]
+    fn minimal_bags() {
+        let (_, games) = parse_games("Game 7: 2 teal, 1 mauve; 5 teal\nGame 8: 3 red").unwrap();
+        assert_eq!(games[0].minimal_bag(&[]), Bag::new([("teal", 5), ("mauve", 1)]));
+        assert_eq!(games[0].minimal_bag(&[]).power(), 5);
+        // Colours a game never draws need no cubes at all
+        assert_eq!(games[1].minimal_bag(&["red", "blue"]).power(), 0);
+    }
+
+    #[test]
+    fn queries() {
+        let input = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+        let (_, games) = parse_games(input).unwrap();
+        assert_eq!(max_draws(&games), Bag::new([("red", 20), ("green", 13), ("blue", 15)]));
+        assert_eq!(round_counts(&games), BTreeMap::from([(2, 1), (3, 4)]));
+
+        let bag = Bag::new([("red", 14), ("green", 13), ("blue", 14)]);
+        assert_eq!(unlocked_by(&games, &bag, "blue"), [4]);
+        assert_eq!(unlocked_by(&games, &bag, "red"), Vec::<u32>::new());
+        // A colour the bag lacks entirely can be added too
+        let bag = Bag::new([("red", 20), ("green", 13)]);
+        assert_eq!(unlocked_by(&games, &bag, "blue"), Vec::<u32>::new());
+        assert_eq!(unlocked_by(&games, &Bag::new([("red", 20), ("blue", 15)]), "green"), Vec::<u32>::new());
+
+        assert_eq!(
+            stats(input, &Bag::new([("red", 14), ("green", 13), ("blue", 14)])),
+            "max draws: 15 blue, 13 green, 20 red
+rounds per game:
+  2 rounds: 1 game
+  3 rounds: 4 games
+one more cube than 14 blue, 13 green, 14 red would make possible:
+  blue: games 4
+  green: no games
+  red: no games"
+        );
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(2);
+        for _ in 0..200 {
+            let input = random_games(&mut rng);
+            let (_, games) = all_consuming(parse_games)(&input).unwrap();
+            let printed = print_games(&games);
+            assert_eq!(printed, input);
+            assert_eq!(all_consuming(parse_games)(&printed).unwrap().1, games);
+
+            let first = &games[0].rounds[0];
+            let cube_text = format!("{} {}", first[0].amount, first[0].color);
+            assert_eq!(all_consuming(cube)(&cube_text).unwrap().1, first[0]);
+            let round_text = input.split(": ").nth(1).unwrap().split("; ").next().unwrap();
+            assert_eq!(&round(round_text).unwrap().1, first);
+        }
+    }
+
+    #[test]
+    fn mutated_games_fail_cleanly() {
+        let mut rng = Rng::new(2);
+        for _ in 0..200 {
+            let input = random_games(&mut rng);
+            for mutated in mutations(&mut rng, &input) {
+                let parsed = all_consuming(parse_games)(&mutated);
+                if mutated.contains(|c: char| !c.is_ascii_alphanumeric() && !" :;,\n".contains(c)) {
+                    assert!(parsed.is_err(), "{mutated}");
+                }
+            }
+
+            if let Some(broken) = replace_one(&mut rng, &input, |_, c| ",;".contains(c), "#") {
+                assert!(all_consuming(parse_games)(&broken).is_err(), "{broken}");
+            }
+            // Enough of every generated colour that only the misspelt one can fail
+            let bag = Bag::new([("red", 99), ("green", 99), ("blue", 99), ("teal", 99)]);
+            let unknown = replace_one(&mut rng, &input, |_, c| c.is_ascii_lowercase(), "X").unwrap();
+            if let Ok((_, games)) = all_consuming(parse_games)(&unknown) {
+                let unknown_colour = |game| matches!(bag.check(game), Err(BagError::UnknownColour { .. }));
+                assert!(games.iter().any(unknown_colour), "{unknown}");
+            }
+
+            let broken = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "#").unwrap();
+            assert!(all_consuming(parse_games)(&broken).is_err(), "{broken}");
+            let overflowing = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "99999999999").unwrap();
+            assert!(all_consuming(parse_games)(&overflowing).is_err(), "{overflowing}");
+        }
+    }
 }
\ No newline at end of file
@@ -1,212 +1,265 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
 
 use crate::problem::Problem;
 
 pub struct DayThree;
 
+/// A run of digits on one row, covering columns `col_start..col_end`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct PartNumber {
+    pub(crate) value: u32,
+    pub(crate) row: usize,
+    pub(crate) col_start: usize,
+    pub(crate) col_end: usize,
+}
+
+/// Any character that is neither a digit nor `.`, at `(row, column)`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Symbol {
+    pub(crate) ch: char,
+    pub(crate) pos: (usize, usize),
+}
+
+/// The numbers and symbols of an engine schematic, indexed by the cells they
+/// cover so that neighbours can be looked up directly.
 #[derive(Debug)]
-enum Value {
-    Empty,
-    Number(u32),
-    Symbol(char),
+pub(crate) struct Schematic {
+    numbers: Vec<PartNumber>,
+    symbols: Vec<Symbol>,
+    number_at: HashMap<(usize, usize), usize>,
+    symbol_at: HashMap<(usize, usize), usize>,
 }
 
-impl Problem for DayThree {
-    fn part_one(&self, input: &str) -> String {
-        //To grid
-        let v = input.lines().enumerate().flat_map(|(y, line)| {
-            line.chars().enumerate().map(|(x, character)| {
-                ((y as i32, x as i32), match character {
-                    '.' => Value::Empty,
-                    c if c.is_ascii_digit() => {
-                        Value::Number(c.to_digit(10).expect("Should be a digit"))
-                    }
-                    c => Value::Symbol(c),
-                })
-            }).collect::<Vec<((i32, i32), Value)>>()
-        }).collect::<BTreeMap<(i32, i32), Value>>();
-
-        let mut numbers: Vec<Vec<((i32, i32), u32)>> = vec![];
-        for ((y, x), value) in v.iter() {
-            if let Value::Number(num) = value {
-                match numbers.iter().last() {
-                    Some(v) => {
-                        let last = v.iter().last();
-                        match last {
-                            Some(((last_x, _), _)) => {
-                                if last_x + 1 == *x {
-                                    let l = numbers.iter_mut().last().expect("Should exist");
-                                    l.push(((*x, *y), *num))
-                                } else {
-                                    numbers.push(vec![((*x, *y), *num)])
-                                }
-                            }
-                            None => unimplemented!("Dont come here")
-                        }
-                    }
-                    None => {
-                        numbers.push(vec![((*x, *y), *num)])
-                    }
-                }
-            }
-        }
-        let mut total = 0;
-        for num_list in numbers {
-            // (x,y)
-            let positions = [
-                (1, 0),
-                (1, -1),
-                (0, -1),
-                (-1, -1),
-                (-1, 0),
-                (-1, 1),
-                (0, 1),
-                (1, 1),
-            ];
-            let num_positions: Vec<(i32, i32)> = num_list
-                .iter()
-                .map(|((y, x), _)| (*x as i32, *y as i32))
-                .collect();
-            let pos_to_check: HashSet<(i32, i32)> = num_list
-                .iter()
-                .flat_map(|(pos, _)| {
-                    positions.iter().map(|outer_pos| {
-                        // outer_pos.x + pos.x, .y + .y
-                        (
-                            outer_pos.0 + pos.1 as i32,
-                            outer_pos.1 + pos.0 as i32,
-                        )
-                    })
-                })
-                .filter(|num| !num_positions.contains(num))
-                .collect::<HashSet<(i32, i32)>>();
-
-            let is_part_num: bool = pos_to_check.iter().any(|pos| {
-                let val = v.get(&pos);
-                if let Some(Value::Symbol(_)) = val {
-                    true
-                } else {
-                    false
-                }
-            });
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SchematicError {
+    /// A number with too many digits for a `u32`, at its 1-based row and
+    /// first column.
+    Overflow { row: usize, col: usize },
+}
 
-            if is_part_num {
-                total += num_list
-                    .iter()
-                    .map(|(_, num)| num.to_string())
-                    .collect::<String>()
-                    .parse::<u32>()
-                    .unwrap();
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchematicError::Overflow { row, col } => {
+                write!(f, "the number at {row}:{col} doesn't fit in a u32")
             }
         }
-        total.to_string()
     }
+}
 
-    fn part_two(&self, input: &str) -> String {
-        //To grid
-        let v = input.lines().enumerate().flat_map(|(y, line)| {
-            line.chars().enumerate().map(|(x, character)| {
-                ((y as i32, x as i32), match character {
-                    '.' => Value::Empty,
-                    c if c.is_ascii_digit() => {
-                        Value::Number(c.to_digit(10).expect("Should be a digit"))
+/// The cells around the block of rows and columns, including the block itself.
+fn neighbourhood(
+    rows: (usize, usize),
+    cols: (usize, usize),
+) -> impl Iterator<Item = (usize, usize)> {
+    (rows.0.saturating_sub(1)..=rows.1 + 1)
+        .flat_map(move |row| (cols.0.saturating_sub(1)..=cols.1 + 1).map(move |col| (row, col)))
+}
+
+impl Schematic {
+    pub(crate) fn parse(input: &str) -> Result<Self, SchematicError> {
+        let mut numbers = vec![];
+        let mut symbols = vec![];
+        for (row, line) in input.lines().enumerate() {
+            let mut current: Option<PartNumber> = None;
+            for (col, character) in line.char_indices() {
+                match (character.to_digit(10), current.as_mut()) {
+                    (Some(digit), Some(number)) => {
+                        number.value = number
+                            .value
+                            .checked_mul(10)
+                            .and_then(|value| value.checked_add(digit))
+                            .ok_or(SchematicError::Overflow { row: row + 1, col: number.col_start + 1 })?;
+                        number.col_end = col + 1;
                     }
-                    c => Value::Symbol(c),
-                })
-            }).collect::<Vec<((i32, i32), Value)>>()
-        }).collect::<BTreeMap<(i32, i32), Value>>();
-
-        let mut numbers: Vec<Vec<((i32, i32), u32)>> = vec![];
-        for ((y, x), value) in v.iter() {
-            if let Value::Number(num) = value {
-                match numbers.iter().last() {
-                    Some(v) => {
-                        let last = v.iter().last();
-                        match last {
-                            Some(((last_x, _), _)) => {
-                                if last_x + 1 == *x {
-                                    let l = numbers.iter_mut().last().expect("Should exist");
-                                    l.push(((*x, *y), *num))
-                                } else {
-                                    numbers.push(vec![((*x, *y), *num)])
-                                }
-                            }
-                            None => unimplemented!("Dont come here")
-                        }
+                    (Some(digit), None) => {
+                        current = Some(PartNumber { value: digit, row, col_start: col, col_end: col + 1 });
                     }
-                    None => {
-                        numbers.push(vec![((*x, *y), *num)])
+                    (None, _) => {
+                        numbers.extend(current.take());
+                        if character != '.' {
+                            symbols.push(Symbol { ch: character, pos: (row, col) });
+                        }
                     }
                 }
             }
+            // A number running to the end of the line ends with it
+            numbers.extend(current);
         }
-        let mut total = 0;
-        for symbol in v.iter().filter(|(_, value)| {
-            match value {
-                Value::Symbol('*') => true,
-                _ => false
-            }
-        }) {
-            // (x,y)
-            let positions = [
-                (1, 0),
-                (1, -1),
-                (0, -1),
-                (-1, -1),
-                (-1, 0),
-                (-1, 1),
-                (0, 1),
-                (1, 1),
-            ];
-
-            let pos_to_check: Vec<(i32, i32)> = positions
-                .iter()
-                .map(|adjacent| {
-                    (
-                        adjacent.0 + symbol.0 .1,
-                        adjacent.1 + symbol.0 .0,
-                    )
-                })
-                .collect();
-
-            let mut numbers_to_symbols = HashSet::new();
-
-            for pos in pos_to_check {
-                for (i, num_list) in numbers.iter().enumerate() {
-                    if num_list
-                        .iter()
-                        .find(|(num_pos, _)| num_pos == &pos)
-                        .is_some()
-                    {
-                        numbers_to_symbols.insert(i);
-                    }
+
+        let number_at = numbers
+            .iter()
+            .enumerate()
+            .flat_map(|(index, number)| {
+                (number.col_start..number.col_end).map(move |col| ((number.row, col), index))
+            })
+            .collect();
+        let symbol_at = symbols
+            .iter()
+            .enumerate()
+            .map(|(index, symbol)| (symbol.pos, index))
+            .collect();
+        Ok(Schematic { numbers, symbols, number_at, symbol_at })
+    }
+
+    /// The symbols touching the number, diagonals included.
+    pub(crate) fn adjacent_symbols(&self, number: &PartNumber) -> Vec<&Symbol> {
+        neighbourhood((number.row, number.row), (number.col_start, number.col_end - 1))
+            .filter_map(|cell| self.symbol_at.get(&cell))
+            .map(|&index| &self.symbols[index])
+            .collect()
+    }
+
+    /// The numbers touching the symbol, diagonals included, each listed once.
+    pub(crate) fn adjacent_numbers(&self, symbol: &Symbol) -> Vec<&PartNumber> {
+        let mut indices = neighbourhood((symbol.pos.0, symbol.pos.0), (symbol.pos.1, symbol.pos.1))
+            .filter_map(|cell| self.number_at.get(&cell).copied())
+            .collect::<Vec<usize>>();
+        indices.sort();
+        indices.dedup();
+        indices.into_iter().map(|index| &self.numbers[index]).collect()
+    }
+
+    /// Numbers next to at least one symbol.
+    pub(crate) fn part_numbers(&self) -> impl Iterator<Item = &PartNumber> {
+        self.numbers
+            .iter()
+            .filter(|number| !self.adjacent_symbols(number).is_empty())
+    }
+
+    /// Numbers next to at least one `ch` symbol.
+    pub(crate) fn numbers_next_to(&self, ch: char) -> Vec<&PartNumber> {
+        self.numbers
+            .iter()
+            .filter(|number| self.adjacent_symbols(number).iter().any(|symbol| symbol.ch == ch))
+            .collect()
+    }
+
+    /// Symbols touching exactly `count` numbers.
+    pub(crate) fn symbols_with(&self, count: usize) -> impl Iterator<Item = &Symbol> {
+        self.symbols
+            .iter()
+            .filter(move |symbol| self.adjacent_numbers(symbol).len() == count)
+    }
+
+    /// Combines the numbers around each symbol in `class` that touches exactly
+    /// `count` of them, and adds up the results. Gear ratios are the products
+    /// around `*` symbols with two numbers.
+    pub(crate) fn total(&self, class: impl Fn(char) -> bool, count: usize, combine: Combine) -> u64 {
+        self.symbols_with(count)
+            .filter(|symbol| class(symbol.ch))
+            .map(|symbol| {
+                let values = self.adjacent_numbers(symbol).into_iter().map(|number| number.value as u64);
+                match combine {
+                    Combine::Sum => values.sum::<u64>(),
+                    Combine::Product => values.product::<u64>(),
                 }
-            }
+            })
+            .sum()
+    }
+}
 
-            let is_gear = numbers_to_symbols.iter().count() == 2;
+/// Sums what [`Schematic::total`] finds around the symbols among `class`, or
+/// around every symbol when `class` is empty.
+pub(crate) fn symbol_total(input: &str, class: &str, count: usize, combine: Combine) -> String {
+    match Schematic::parse(input) {
+        Ok(schematic) => schematic
+            .total(|ch| class.is_empty() || class.contains(ch), count, combine)
+            .to_string(),
+        Err(e) => format!("error: {e}"),
+    }
+}
 
-            if is_gear {
-                total += numbers_to_symbols
+/// How [`Schematic::total`] combines the numbers around one symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Combine {
+    Sum,
+    Product,
+}
+
+/// Lists every number with the symbols that make it a part number, or as
+/// excluded when there are none, followed by the numbers around each kind of
+/// symbol.
+pub(crate) fn report(input: &str) -> String {
+    let schematic = match Schematic::parse(input) {
+        Ok(schematic) => schematic,
+        Err(e) => return format!("error: {e}"),
+    };
+    let mut counted = 0;
+    let mut excluded = 0;
+    let mut lines = schematic
+        .numbers
+        .iter()
+        .map(|number| {
+            let symbols = schematic.adjacent_symbols(number);
+            let verdict = if symbols.is_empty() {
+                excluded += number.value;
+                "excluded, no adjacent symbol".to_string()
+            } else {
+                counted += number.value;
+                let symbols = symbols
                     .iter()
-                    .map(|index| {
-                        numbers[*index]
-                            .iter()
-                            .map(|(_, num)| num.to_string())
-                            .collect::<String>()
-                            .parse::<usize>()
-                            .unwrap()
-                    })
-                    .product::<usize>()
-            }
+                    .map(|symbol| format!("{} at {}:{}", symbol.ch, symbol.pos.0 + 1, symbol.pos.1 + 1))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("counted, next to {symbols}")
+            };
+            format!(
+                "{:>6} at {}:{}-{}  {verdict}",
+                number.value,
+                number.row + 1,
+                number.col_start + 1,
+                number.col_end
+            )
+        })
+        .collect::<Vec<String>>();
+    lines.push(format!("counted {counted}, excluded {excluded}"));
+
+    let mut classes = schematic.symbols.iter().map(|symbol| symbol.ch).collect::<Vec<char>>();
+    classes.sort();
+    classes.dedup();
+    for ch in classes {
+        let numbers = schematic.numbers_next_to(ch);
+        let sum = numbers.iter().map(|number| number.value).sum::<u32>();
+        lines.push(format!("next to {ch}: {} numbers summing to {sum}", numbers.len()));
+    }
+    lines.join("\n")
+}
+
+impl Problem for DayThree {
+    fn part_one(&self, input: &str) -> String {
+        match Schematic::parse(input) {
+            Ok(schematic) => schematic
+                .part_numbers()
+                .map(|number| number.value)
+                .sum::<u32>()
+                .to_string(),
+            Err(e) => format!("error: {e}"),
         }
-        total.to_string()
+    }
+
+    fn part_two(&self, input: &str) -> String {
+        symbol_total(input, "*", 2, Combine::Product)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use rstest::rstest;
+
     use super::*;
 
+    #[rstest]
+    #[case::example(include_str!("examples/example.txt"), "4361", "467835")]
+    #[case::line_ends(include_str!("examples/line_ends.txt"), "19", "84")]
+    #[case::consecutive_rows(include_str!("examples/consecutive_rows.txt"), "87", "702")]
+    #[case::border_symbols(include_str!("examples/border_symbols.txt"), "10", "6")]
+    fn examples(#[case] input: &str, #[case] part_one: &str, #[case] part_two: &str) {
+        assert_eq!(DayThree.part_one(input), part_one);
+        assert_eq!(DayThree.part_two(input), part_two);
+    }
+
     #[test]
     fn _part_one() {
         let input = "467..114..
@@ -221,4 +274,70 @@ mod tests {
 .664.598..";
         assert_eq!(DayThree.part_one(input), "4361")
     }
+
+    #[test]
+    fn spans() {
+        let schematic = Schematic::parse("..12\n34*.\n5").unwrap();
+        assert_eq!(
+            schematic.numbers,
+            [
+                PartNumber { value: 12, row: 0, col_start: 2, col_end: 4 },
+                PartNumber { value: 34, row: 1, col_start: 0, col_end: 2 },
+                PartNumber { value: 5, row: 2, col_start: 0, col_end: 1 },
+            ]
+        );
+        assert_eq!(schematic.symbols, [Symbol { ch: '*', pos: (1, 2) }]);
+        // 12 ends one row and 34 starts the next; they stay separate numbers
+        assert_eq!(
+            schematic.adjacent_numbers(&schematic.symbols[0]).iter().map(|number| number.value).collect::<Vec<u32>>(),
+            [12, 34]
+        );
+        assert_eq!(DayThree.part_one("..12\n34*.\n5"), "46");
+    }
+
+    #[test]
+    fn oversized_numbers() {
+        assert_eq!(Schematic::parse("4294967295*").unwrap().numbers[0].value, u32::MAX);
+        let input = "..\n.4294967296*";
+        assert_eq!(Schematic::parse(input).unwrap_err(), SchematicError::Overflow { row: 2, col: 2 });
+        assert_eq!(DayThree.part_one(input), "error: the number at 2:2 doesn't fit in a u32");
+        assert_eq!(report(input), "error: the number at 2:2 doesn't fit in a u32");
+    }
+
+    #[test]
+    fn queries() {
+        let input = "467..114..
+...*......
+..35..633.
+......#...
+617*......
+.....+.58.
+..592.....
+......755.
+...$.*....
+.664.598..";
+        let schematic = Schematic::parse(input).unwrap();
+        let values = |numbers: Vec<&PartNumber>| numbers.iter().map(|number| number.value).collect::<Vec<u32>>();
+        assert_eq!(values(schematic.numbers_next_to('*')), [467, 35, 617, 755, 598]);
+        assert_eq!(values(schematic.numbers_next_to('#')), [633]);
+        assert_eq!(
+            schematic.symbols_with(2).map(|symbol| symbol.pos).collect::<Vec<(usize, usize)>>(),
+            [(1, 3), (8, 5)]
+        );
+        assert_eq!(schematic.symbols_with(1).count(), 4);
+        assert_eq!(schematic.total(|ch| ch == '*', 2, Combine::Product), 467835);
+        assert_eq!(schematic.total(|ch| ch == '*', 2, Combine::Sum), 467 + 35 + 755 + 598);
+        assert_eq!(schematic.total(|ch| ch != '*', 1, Combine::Sum), 633 + 592 + 664);
+    }
+
+    #[test]
+    fn counted_and_excluded() {
+        assert_eq!(
+            report("467..114..\n...*......"),
+            "   467 at 1:1-3  counted, next to * at 2:4
+   114 at 1:6-8  excluded, no adjacent symbol
+counted 467, excluded 114
+next to *: 1 numbers summing to 467"
+        );
+    }
 }
\ No newline at end of file
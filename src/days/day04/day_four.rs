@@ -1,9 +1,11 @@
-use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt;
 
 use nom::IResult;
 use nom::bytes::complete::tag;
 use nom::character::complete;
 use nom::character::complete::space1;
+use nom::combinator::all_consuming;
 use nom::multi::separated_list1;
 use nom::sequence::{preceded, separated_pair, terminated, tuple};
 
@@ -16,7 +18,7 @@ struct Card {
     number: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Game {
     cards_in_hand: Vec<Card>,
     cards_in_deck: Vec<Card>,
@@ -25,36 +27,96 @@ struct Game {
 
 impl Game {
     fn get_score(&self) -> u32 {
-        let score = self
-            .cards_in_hand
-            .iter()
-            .filter_map(|x| {
-                match self.cards_in_deck.contains(x) {
-                    true => Some(1),
-                    false => None
-                }
-            })
-            .count() as u32;
-        match score {
+        match self.get_matches() {
             s if s >= 1 => 2u32.pow(s - 1),
             _ => 0
         }
     }
 
     fn get_matches(&self) -> u32 {
+        let deck = self
+            .cards_in_deck
+            .iter()
+            .map(|card| card.number)
+            .collect::<HashSet<u32>>();
         self
             .cards_in_hand
             .iter()
-            .filter_map(|x| {
-                match self.cards_in_deck.contains(x) {
-                    true => Some(1),
-                    false => None
-                }
-            })
+            .filter(|card| deck.contains(&card.number))
             .count() as u32
     }
 }
 
+/// One card winning copies of a later one.
+#[derive(Debug, PartialEq, Eq)]
+struct Spawn {
+    from: u32,
+    to: u32,
+    copies: u32,
+}
+
+/// How many of each card there are once every copy has been scratched, in
+/// the order the cards were listed.
+#[derive(Debug, PartialEq, Eq)]
+struct Cascade {
+    ids: Vec<u32>,
+    matches: Vec<u32>,
+    copies: Vec<u32>,
+    spawns: Vec<Spawn>,
+}
+
+impl Cascade {
+    /// Every copy of a card with `n` matches wins a copy of each of the next
+    /// `n` cards in the list, whatever their ids, and never past the end.
+    fn new(games: &[Game]) -> Self {
+        let ids = games.iter().map(|game| game.id).collect::<Vec<u32>>();
+        let matches = games.iter().map(Game::get_matches).collect::<Vec<u32>>();
+        let mut copies = vec![1; games.len()];
+        let mut spawns = vec![];
+        for index in 0..games.len() {
+            let won = (index + 1)..(index + 1 + matches[index] as usize).min(games.len());
+            for next in won {
+                copies[next] += copies[index];
+                spawns.push(Spawn { from: ids[index], to: ids[next], copies: copies[index] });
+            }
+        }
+        Cascade { ids, matches, copies, spawns }
+    }
+
+    fn total(&self) -> u32 {
+        self.copies.iter().sum()
+    }
+}
+
+/// Each card's matches and final number of copies, with the copies it won.
+pub(crate) fn trace(input: &str) -> String {
+    let (games, _) = parse_scratchcards(input, Validation::Strict)
+        .unwrap_or_else(|e| panic!("{e}"));
+    let cascade = Cascade::new(&games);
+
+    let mut lines = cascade
+        .ids
+        .iter()
+        .zip(&cascade.matches)
+        .zip(&cascade.copies)
+        .map(|((id, matches), copies)| {
+            let won = cascade
+                .spawns
+                .iter()
+                .filter(|spawn| spawn.from == *id)
+                .map(|spawn| spawn.to.to_string())
+                .collect::<Vec<String>>();
+            let won = match won.is_empty() {
+                true => "wins nothing".to_string(),
+                false => format!("each wins a copy of cards {}", won.join(", ")),
+            };
+            format!("card {id}: {matches} matches, {copies} copies, {won}")
+        })
+        .collect::<Vec<String>>();
+    lines.push(format!("total cards: {}", cascade.total()));
+    lines.join("\n")
+}
+
 fn parse_cards(line: &str) -> IResult<&str, Vec<u32>> {
     let (line, cards) = preceded(space1, separated_list1(space1, complete::u32))(line)?;
     Ok((line, cards))
@@ -72,17 +134,129 @@ fn parse_game(line: &str) -> IResult<&str, Game> {
     Ok((line, Game { cards_in_hand, cards_in_deck, id }))
 }
 
+/// Which list on a card a number appears in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Side {
+    Winning,
+    Held,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CardError {
+    Malformed { line: usize },
+    DuplicateNumber { card: u32, side: Side, number: u32 },
+    /// Every card should have as many winning and held numbers as the first.
+    InconsistentWidth { card: u32, expected: (usize, usize), found: (usize, usize) },
+    /// Ids have to increase down the list, though they may skip.
+    OutOfOrder { card: u32, previous: u32 },
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardError::Malformed { line } => write!(f, "line {line} is not a scratchcard"),
+            CardError::DuplicateNumber { card, side, number } => {
+                let side = match side {
+                    Side::Winning => "winning",
+                    Side::Held => "held",
+                };
+                write!(f, "card {card} lists {number} twice among its {side} numbers")
+            }
+            CardError::InconsistentWidth { card, expected, found } => write!(
+                f,
+                "card {card} has {} winning and {} held numbers, but the first card has {} and {}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            CardError::OutOfOrder { card, previous } => {
+                write!(f, "card {card} comes after card {previous}")
+            }
+        }
+    }
+}
+
+/// How [`parse_scratchcards`] treats a card that fails validation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Validation {
+    Strict,
+    /// Drop repeated numbers and carry on, returning every problem as a
+    /// warning instead.
+    Lenient,
+}
+
+/// Removes repeated numbers from the list, keeping the first of each.
+fn dedup(cards: &mut Vec<Card>) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    let mut duplicates = vec![];
+    cards.retain(|card| {
+        let first = seen.insert(card.number);
+        if !first {
+            duplicates.push(card.number);
+        }
+        first
+    });
+    duplicates
+}
+
+/// Parses every card and checks them against each other. In strict mode the
+/// first problem is an error; in lenient mode duplicates are dropped and all
+/// problems come back as warnings next to the cards.
+fn parse_scratchcards(input: &str, validation: Validation) -> Result<(Vec<Game>, Vec<CardError>), CardError> {
+    let mut games: Vec<Game> = vec![];
+    let mut problems = vec![];
+    let mut expected = None;
+    for (index, line) in input.lines().enumerate() {
+        let (_, mut game) = all_consuming(parse_game)(line)
+            .map_err(|_| CardError::Malformed { line: index + 1 })?;
+
+        // Widths are compared as written, before any duplicates are dropped
+        let found = (game.cards_in_hand.len(), game.cards_in_deck.len());
+        let expected = *expected.get_or_insert(found);
+        for (side, cards) in [(Side::Winning, &mut game.cards_in_hand), (Side::Held, &mut game.cards_in_deck)] {
+            problems.extend(dedup(cards).into_iter().map(|number| {
+                CardError::DuplicateNumber { card: game.id, side, number }
+            }));
+        }
+        if found != expected {
+            problems.push(CardError::InconsistentWidth { card: game.id, expected, found });
+        }
+        if let Some(previous) = games.last().map(|previous| previous.id) {
+            if game.id <= previous {
+                problems.push(CardError::OutOfOrder { card: game.id, previous });
+            }
+        }
+
+        if validation == Validation::Strict && !problems.is_empty() {
+            return Err(problems.remove(0));
+        }
+        games.push(game);
+    }
+    Ok((games, problems))
+}
+
+/// Validates the cards, listing every problem found.
+pub(crate) fn check(input: &str, validation: Validation) -> String {
+    match parse_scratchcards(input, validation) {
+        Ok((games, warnings)) if warnings.is_empty() => format!("all {} cards are valid", games.len()),
+        Ok((games, warnings)) => {
+            let mut lines = warnings
+                .iter()
+                .map(|warning| format!("warning: {warning}"))
+                .collect::<Vec<String>>();
+            lines.push(format!("{} cards read with {} warnings", games.len(), warnings.len()));
+            lines.join("\n")
+        }
+        Err(e) => format!("error: {e}"),
+    }
+}
+
 impl Problem for DayFour {
     fn part_one(&self, input: &str) -> String {
-        let games = input
-            .lines()
-            .map(|line|
-                parse_game(line).expect("Must parse"))
-            .collect::<Vec<(&str, Game)>>();
+        let (games, _) = parse_scratchcards(input, Validation::Strict)
+            .unwrap_or_else(|e| panic!("{e}"));
 
         let x = games
             .iter()
-            .map(|(_, game)| game.get_score())
+            .map(|game| game.get_score())
             .collect::<Vec<u32>>()
             .iter()
             .sum::<u32>();
@@ -90,46 +264,47 @@ impl Problem for DayFour {
     }
 
     fn part_two(&self, input: &str) -> String {
-        let games = input
-            .lines()
-            .map(|line|
-                parse_game(line).expect("Must parse"))
-            .collect::<Vec<(&str, Game)>>();
+        let (games, _) = parse_scratchcards(input, Validation::Strict)
+            .unwrap_or_else(|e| panic!("{e}"));
 
-        let data = games
-            .iter()
-            .map(|(_, game)| game.get_matches())
-            .collect::<Vec<u32>>();
-
-        let store = games
-            .iter()
-            .map(|(_, game)| ((game.id  - 1) as usize, 1))
-            .collect::<BTreeMap<usize, u32>>();
-
-        let result = data
-            .iter()
-            .enumerate()
-            .fold(store, |mut acc, (index, card_score)| {
-                let to_add = *acc.get(&index).unwrap();
-
-                for i in (index + 1)..(index + 1 + *card_score as usize)
-                {
-                    acc.entry(i).and_modify(|value| {
-                        *value += to_add;
-                    });
-                }
-                acc
-            })
-            .values()
-            .sum::<u32>();
-
-        result.to_string()
+        Cascade::new(&games).total().to_string()
     }
 }
 
+#[cfg(test)]
 mod tests {
-    use crate::days::day04::day_four::DayFour;
+    use nom::combinator::all_consuming;
+
+    use crate::days::day04::day_four::{Card, CardError, Cascade, DayFour, Game, Side, Spawn, Validation, check, parse_game, parse_scratchcards, trace};
     use crate::problem::Problem;
+    use crate::testing::{Rng, mutations, replace_one};
+
+    /// Cards laid out like the puzzle's, with numbers right-aligned in
+    /// columns of the same width throughout.
+    fn random_cards(rng: &mut Rng) -> String {
+        let (winning, held) = (rng.between(1, 6), rng.between(1, 10));
+        let mut id = 0;
+        (0..rng.between(1, 8))
+            .map(|_| {
+                id += rng.between(1, 3);
+                let mut numbers = (1..100).collect::<Vec<u32>>();
+                let mut side = |count: usize| {
+                    (0..count)
+                        .map(|_| format!("{:>2}", numbers.remove(rng.below(numbers.len() as u64) as usize)))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                };
+                let winning = side(winning);
+                format!("Card {id:>3}: {winning} | {}", side(held))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn print_card(game: &Game) -> String {
+        let side = |cards: &[Card]| cards.iter().map(|card| card.number.to_string()).collect::<Vec<String>>().join(" ");
+        format!("Card {}: {} | {}", game.id, side(&game.cards_in_hand), side(&game.cards_in_deck))
+    }
 
     #[test]
     fn part_one() {
@@ -152,4 +327,111 @@ Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
 Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
         assert_eq!(DayFour.part_two(input), "30")
     }
+
+    #[test]
+    fn cascade() {
+        // Ids needn't start at 1 or follow on from each other
+        let input = "Card 10: 1 2 | 1 2 3
+Card 12: 4 5 | 4 8 9
+Card 30: 6 7 | 1 8 9
+Card 31: 1 2 | 1 2 3";
+        let games = input
+            .lines()
+            .map(|line| parse_game(line).unwrap().1)
+            .collect::<Vec<Game>>();
+        let cascade = Cascade::new(&games);
+        assert_eq!(cascade.matches, [2, 1, 0, 2]);
+        assert_eq!(cascade.copies, [1, 2, 4, 1]);
+        assert_eq!(
+            cascade.spawns,
+            [
+                Spawn { from: 10, to: 12, copies: 1 },
+                Spawn { from: 10, to: 30, copies: 1 },
+                Spawn { from: 12, to: 30, copies: 2 },
+            ]
+        );
+        assert_eq!(DayFour.part_two(input), "8");
+        assert_eq!(
+            trace(input),
+            "card 10: 2 matches, 1 copies, each wins a copy of cards 12, 30
+card 12: 1 matches, 2 copies, each wins a copy of cards 30
+card 30: 0 matches, 4 copies, wins nothing
+card 31: 2 matches, 1 copies, wins nothing
+total cards: 8"
+        );
+    }
+
+    #[test]
+    fn validation() {
+        let input = "Card 1: 41 48 41 | 83 86 6 48
+Card 2: 13 32 20 | 61 30 68 32";
+        assert_eq!(
+            parse_scratchcards(input, Validation::Strict).unwrap_err(),
+            CardError::DuplicateNumber { card: 1, side: Side::Winning, number: 41 }
+        );
+        let (games, warnings) = parse_scratchcards(input, Validation::Lenient).unwrap();
+        assert_eq!(warnings, [CardError::DuplicateNumber { card: 1, side: Side::Winning, number: 41 }]);
+        assert_eq!(games[0].cards_in_hand, [Card { number: 41 }, Card { number: 48 }]);
+        // 41 no longer counts twice
+        assert_eq!(games[0].get_matches(), 1);
+
+        let input = "Card 1: 1 2 | 3 4
+Card 3: 1 2 | 3
+Card 2: 1 2 | 3 4 4";
+        assert_eq!(
+            parse_scratchcards(input, Validation::Strict).unwrap_err(),
+            CardError::InconsistentWidth { card: 3, expected: (2, 2), found: (2, 1) }
+        );
+        assert_eq!(
+            check(input, Validation::Lenient),
+            "warning: card 3 has 2 winning and 1 held numbers, but the first card has 2 and 2
+warning: card 2 lists 4 twice among its held numbers
+warning: card 2 has 2 winning and 3 held numbers, but the first card has 2 and 2
+warning: card 2 comes after card 3
+3 cards read with 4 warnings"
+        );
+        assert_eq!(
+            check("Card 1: 1 2 | 3 4\nCard 2 1 2 | 3 4", Validation::Lenient),
+            "error: line 2 is not a scratchcard"
+        );
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(4);
+        for _ in 0..200 {
+            let input = random_cards(&mut rng);
+            for line in input.lines() {
+                let (_, game) = all_consuming(parse_game)(line).unwrap();
+                let printed = print_card(&game);
+                assert_eq!(all_consuming(parse_game)(&printed).unwrap().1, game);
+            }
+            let (games, warnings) = parse_scratchcards(&input, Validation::Strict).unwrap();
+            assert!(warnings.is_empty());
+            assert_eq!(games.len(), input.lines().count());
+        }
+    }
+
+    #[test]
+    fn mutated_cards_fail_cleanly() {
+        let mut rng = Rng::new(4);
+        for _ in 0..200 {
+            let input = random_cards(&mut rng);
+            for mutated in mutations(&mut rng, &input) {
+                let _ = parse_scratchcards(&mutated, Validation::Strict);
+                let lenient = parse_scratchcards(&mutated, Validation::Lenient);
+                if mutated.contains(|c: char| !c.is_ascii_digit() && !"Card:| \n".contains(c)) {
+                    assert!(lenient.is_err(), "{mutated}");
+                }
+            }
+
+            let broken = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "#").unwrap();
+            assert_eq!(
+                parse_scratchcards(&broken, Validation::Lenient).unwrap_err(),
+                CardError::Malformed { line: broken.lines().position(|line| line.contains('#')).unwrap() + 1 }
+            );
+            let overflowing = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "99999999999").unwrap();
+            assert!(parse_scratchcards(&overflowing, Validation::Lenient).is_err(), "{overflowing}");
+        }
+    }
 }
\ No newline at end of file
@@ -1,9 +1,12 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
 use std::ops::Range;
 
+use itertools::Itertools;
 use nom::{IResult, Parser};
-use nom::bytes::complete::take_until;
 use nom::character::complete;
-use nom::character::complete::{line_ending, space1};
+use nom::character::complete::{alpha1, line_ending, multispace1, space1};
+use nom::combinator::map_opt;
 use nom::multi::{many1, separated_list1};
 use nom::sequence::{separated_pair, tuple};
 use nom_supreme::{ParserExt, tag::complete::tag};
@@ -15,12 +18,17 @@ pub(crate) struct DayFive;
 
 // struct SeedId(u32)
 
-#[derive(Debug)]
-struct SeedMap {
+#[derive(Debug, PartialEq)]
+struct SeedMap<'a> {
+    source: &'a str,
+    destination: &'a str,
     mappings: Vec<(Range<u64>, Range<u64>)>,
+    /// Whether values outside every source range keep their value. Only
+    /// inverted maps turn this off, as they spell the identity out instead.
+    passthrough: bool,
 }
 
-impl SeedMap {
+impl<'a> SeedMap<'a> {
     fn translate(&self, src: u64) -> u64 {
         let valid = self.mappings.iter().find(|(src_range, _)| {
             src_range.contains(&src)
@@ -34,39 +42,424 @@ impl SeedMap {
 
         dst_range.start + offset
     }
+
+    /// Every value `src` maps onto. Forward maps always give exactly one,
+    /// inverted maps give one per overlapping range and none for values that
+    /// nothing maps onto.
+    fn lookup(&self, src: u64) -> Vec<u64> {
+        let mut values = self
+            .mappings
+            .iter()
+            .filter(|(src_range, _)| src_range.contains(&src))
+            .map(|(src_range, dst_range)| dst_range.start + (src - src_range.start))
+            .collect::<Vec<u64>>();
+        if values.is_empty() && self.passthrough {
+            values.push(src);
+        }
+        values
+    }
+
+    /// Every value in `range` mapped a piece at a time, as pairs of the input
+    /// values and the values they map onto. Values nothing maps are left
+    /// out, and overlapping inverted ranges give one pair each.
+    fn lookup_range(&self, range: &Range<u64>) -> Vec<(Range<u64>, Range<u64>)> {
+        let passthrough = if self.passthrough { self.gaps() } else { vec![] };
+        self.mappings
+            .iter()
+            .cloned()
+            .chain(passthrough.into_iter().map(|gap| (gap.clone(), gap)))
+            .filter_map(|(src_range, dst_range)| {
+                let start = range.start.max(src_range.start);
+                let end = range.end.min(src_range.end);
+                (start < end).then(|| {
+                    let offset = start - src_range.start;
+                    let length = end - start;
+                    (start..end, dst_range.start + offset..dst_range.start + offset + length)
+                })
+            })
+            .collect()
+    }
+
+    /// The parts of `0..u64::MAX` not covered by any source range, i.e. the
+    /// values this map passes through unchanged.
+    fn gaps(&self) -> Vec<Range<u64>> {
+        let mut covered = self
+            .mappings
+            .iter()
+            .map(|(src_range, _)| src_range.clone())
+            .collect::<Vec<Range<u64>>>();
+        covered.sort_by_key(|range| range.start);
+
+        let mut gaps = vec![];
+        let mut start = 0;
+        for range in covered {
+            if range.start > start {
+                gaps.push(start..range.start);
+            }
+            start = start.max(range.end);
+        }
+        if start < u64::MAX {
+            gaps.push(start..u64::MAX);
+        }
+        gaps
+    }
+
+    /// The map going from `destination` back to `source`. Values the forward
+    /// map passes through are added as explicit identity ranges, so a value
+    /// that is only reachable through a mapping doesn't also map back onto
+    /// itself.
+    fn inverse(&self) -> SeedMap<'a> {
+        let mut mappings = self
+            .mappings
+            .iter()
+            .map(|(src_range, dst_range)| (dst_range.clone(), src_range.clone()))
+            .collect::<Vec<(Range<u64>, Range<u64>)>>();
+        mappings.extend(self.gaps().into_iter().map(|gap| (gap.clone(), gap)));
+
+        SeedMap {
+            source: self.destination,
+            destination: self.source,
+            mappings,
+            passthrough: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum AlmanacError {
+    UnknownCategory(String),
+    DuplicateMap { source: String, destination: String },
+    Cycle(Vec<String>),
+    NoPath { from: String, to: String },
+    /// A backward step was passed where only forward ones can be followed.
+    BackwardStep { source: String, destination: String },
+}
+
+impl fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlmanacError::UnknownCategory(category) => {
+                write!(f, "category `{category}` does not appear in any map")
+            }
+            AlmanacError::DuplicateMap { source, destination } => {
+                write!(f, "map `{source}-to-{destination}` is defined more than once")
+            }
+            AlmanacError::Cycle(chain) => {
+                write!(f, "category chain is cyclic: {}", chain.join(" -> "))
+            }
+            AlmanacError::NoPath { from, to } => {
+                write!(f, "no chain of maps connects `{from}` and `{to}`")
+            }
+            AlmanacError::BackwardStep { source, destination } => write!(
+                f,
+                "map `{source}-to-{destination}` is walked backwards and can map onto several values"
+            ),
+        }
+    }
+}
+
+/// Which way a map is applied while walking a category path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    Forward,
+    Backward,
+}
+
+/// One hop of a category path: the index of the map in the almanac and the
+/// direction it is walked in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Step {
+    map: usize,
+    orientation: Orientation,
+}
+
+/// The maps of an almanac, keyed by their `source-to-destination` header so
+/// lookups don't depend on the order the maps appear in.
+#[derive(Debug)]
+struct Almanac<'a> {
+    maps: Vec<SeedMap<'a>>,
+    inverses: Vec<SeedMap<'a>>,
+    by_source: BTreeMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> Almanac<'a> {
+    fn new(maps: Vec<SeedMap<'a>>) -> Result<Self, AlmanacError> {
+        let mut by_source: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        let mut seen = BTreeSet::new();
+        for (index, map) in maps.iter().enumerate() {
+            if !seen.insert((map.source, map.destination)) {
+                return Err(AlmanacError::DuplicateMap {
+                    source: map.source.to_string(),
+                    destination: map.destination.to_string(),
+                });
+            }
+            by_source.entry(map.source).or_default().push(index);
+        }
+
+        let inverses = maps.iter().map(SeedMap::inverse).collect();
+        let almanac = Almanac { maps, inverses, by_source };
+        almanac.check_acyclic()?;
+        Ok(almanac)
+    }
+
+    fn categories(&self) -> BTreeSet<&'a str> {
+        self.maps
+            .iter()
+            .flat_map(|map| [map.source, map.destination])
+            .collect()
+    }
+
+    fn outgoing(&self, category: &str) -> impl Iterator<Item = &SeedMap<'a>> {
+        self.by_source
+            .get(category)
+            .into_iter()
+            .flatten()
+            .map(|index| &self.maps[*index])
+    }
+
+    fn check_acyclic(&self) -> Result<(), AlmanacError> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Visiting,
+            Done,
+        }
+
+        fn visit<'a>(
+            almanac: &Almanac<'a>,
+            category: &'a str,
+            marks: &mut BTreeMap<&'a str, Mark>,
+            stack: &mut Vec<&'a str>,
+        ) -> Result<(), AlmanacError> {
+            match marks.get(category) {
+                Some(Mark::Done) => return Ok(()),
+                Some(Mark::Visiting) => {
+                    let start = stack
+                        .iter()
+                        .position(|c| *c == category)
+                        .expect("visiting categories are on the stack");
+                    let mut chain = stack[start..]
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<String>>();
+                    chain.push(category.to_string());
+                    return Err(AlmanacError::Cycle(chain));
+                }
+                None => {}
+            }
+
+            marks.insert(category, Mark::Visiting);
+            stack.push(category);
+            for map in almanac.outgoing(category) {
+                visit(almanac, map.destination, marks, stack)?;
+            }
+            stack.pop();
+            marks.insert(category, Mark::Done);
+            Ok(())
+        }
+
+        let mut marks = BTreeMap::new();
+        for category in self.categories() {
+            visit(self, category, &mut marks, &mut vec![])?;
+        }
+        Ok(())
+    }
+
+    /// Shortest chain of maps leading from `from` to `to`, following the maps
+    /// in their own direction.
+    fn forward_path(&self, from: &str, to: &str) -> Option<Vec<usize>> {
+        let mut previous: BTreeMap<&str, (&str, usize)> = BTreeMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(category) = queue.pop_front() {
+            if category == to {
+                let mut path = vec![];
+                let mut current = to;
+                while let Some((parent, map)) = previous.get(current) {
+                    path.push(*map);
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for index in self.by_source.get(category).into_iter().flatten() {
+                let next = self.maps[*index].destination;
+                if next != from && !previous.contains_key(next) {
+                    previous.insert(next, (category, *index));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Resolves the maps needed to go from one category to another. When no
+    /// forward chain exists the reverse chain is walked backwards instead,
+    /// e.g. `location` to `seed`.
+    fn path(&self, from: &str, to: &str) -> Result<Vec<Step>, AlmanacError> {
+        let categories = self.categories();
+        for category in [from, to] {
+            if !categories.contains(category) {
+                return Err(AlmanacError::UnknownCategory(category.to_string()));
+            }
+        }
+
+        if let Some(path) = self.forward_path(from, to) {
+            return Ok(path
+                .into_iter()
+                .map(|map| Step { map, orientation: Orientation::Forward })
+                .collect());
+        }
+        if let Some(path) = self.forward_path(to, from) {
+            return Ok(path
+                .into_iter()
+                .rev()
+                .map(|map| Step { map, orientation: Orientation::Backward })
+                .collect());
+        }
+        Err(AlmanacError::NoPath { from: from.to_string(), to: to.to_string() })
+    }
+
+    /// Follows a forward path resolved by [`Almanac::path`]. Backward steps
+    /// can map onto several values, so paths with them need `follow`.
+    fn translate(&self, path: &[Step], value: u64) -> Result<u64, AlmanacError> {
+        path.iter().try_fold(value, |value, step| {
+            let map = &self.maps[step.map];
+            match step.orientation {
+                Orientation::Forward => Ok(map.translate(value)),
+                Orientation::Backward => Err(AlmanacError::BackwardStep {
+                    source: map.source.to_string(),
+                    destination: map.destination.to_string(),
+                }),
+            }
+        })
+    }
+
+    /// Follows a path in either orientation, walking backward steps through
+    /// the inverted maps. Returns every value reachable from `value`, sorted.
+    fn follow(&self, path: &[Step], value: u64) -> Vec<u64> {
+        path.iter().fold(vec![value], |values, step| {
+            let map = match step.orientation {
+                Orientation::Forward => &self.maps[step.map],
+                Orientation::Backward => &self.inverses[step.map],
+            };
+            values
+                .into_iter()
+                .flat_map(|value| map.lookup(value))
+                .sorted()
+                .dedup()
+                .collect()
+        })
+    }
+
+    /// Follows a path like [`Almanac::follow`], but for a whole range of
+    /// values at once. Returns pairs of input values and the values they
+    /// reach, split wherever a map splits them.
+    fn follow_range(&self, path: &[Step], range: Range<u64>) -> Vec<(Range<u64>, Range<u64>)> {
+        path.iter().fold(vec![(range.clone(), range)], |pieces, step| {
+            let map = match step.orientation {
+                Orientation::Forward => &self.maps[step.map],
+                Orientation::Backward => &self.inverses[step.map],
+            };
+            pieces
+                .into_iter()
+                .flat_map(|(input, current)| {
+                    map.lookup_range(&current).into_iter().map(move |(from, to)| {
+                        let start = input.start + (from.start - current.start);
+                        (start..start + (from.end - from.start), to)
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// Every value that `translate(path, _)` maps onto `value`, e.g. the
+    /// seeds ending up at a given location.
+    fn invert_translate(&self, path: &[Step], value: u64) -> Vec<u64> {
+        self.follow(&reversed(path), value)
+    }
+
+    fn lowest_location(&self, seeds: &[Range<u64>], strategy: Strategy) -> Result<Option<u64>, AlmanacError> {
+        let path = self.path("seed", "location")?;
+        Ok(match strategy {
+            Strategy::Forward => seeds
+                .to_vec()
+                .into_par_iter()
+                .flat_map(|range| range)
+                .map(|seed| self.translate(&path, seed))
+                .try_reduce_with(|a, b| Ok(a.min(b)))
+                .transpose()?,
+            Strategy::Backward => self
+                .follow_range(&reversed(&path), 0..u64::MAX)
+                .into_iter()
+                .flat_map(|(locations, origins)| {
+                    // Both ranges have the same length, offset by a constant
+                    seeds.iter().filter_map(move |range| {
+                        let start = range.start.max(origins.start);
+                        (start < range.end.min(origins.end))
+                            .then_some(locations.start + (start - origins.start))
+                    })
+                })
+                .min(),
+        })
+    }
+}
+
+/// The steps of `path` walked the other way round, from its end to its start.
+fn reversed(path: &[Step]) -> Vec<Step> {
+    path.iter()
+        .rev()
+        .map(|step| Step {
+            map: step.map,
+            orientation: match step.orientation {
+                Orientation::Forward => Orientation::Backward,
+                Orientation::Backward => Orientation::Forward,
+            },
+        })
+        .collect()
+}
+
+/// How [`Almanac::lowest_location`] searches for the answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    /// Translates every seed and keeps the minimum. Real seed ranges hold
+    /// billions of seeds, so this mostly serves to check `Backward`.
+    Forward,
+    /// Pulls every location back through the inverted maps a range at a
+    /// time and keeps the lowest one whose seeds overlap the seed ranges.
+    Backward,
 }
 
 fn line_p1(
     input: &str,
 ) -> IResult<&str, (Range<u64>, Range<u64>)> {
-    let (input, (destination, source, num)) = tuple((
-        complete::u64,
-        complete::u64.preceded_by(tag(" ")),
-        complete::u64.preceded_by(tag(" ")),
-    ))(input)?;
-
-    Ok((
-        input,
-        (
-            source..(source + num),
-            destination..(destination + num),
-        ),
-    ))
-}
-
-fn seed_map_p1(input: &str) -> IResult<&str, SeedMap> {
-    take_until("map:")
-        .precedes(tag("map:"))
-        .precedes(
-            many1(line_ending.precedes(line_p1))
-                .map(|mappings| SeedMap { mappings }),
-        )
-        .parse(input)
+    // A range running past u64::MAX can't be represented, so it doesn't parse
+    map_opt(
+        tuple((
+            complete::u64,
+            complete::u64.preceded_by(tag(" ")),
+            complete::u64.preceded_by(tag(" ")),
+        )),
+        |(destination, source, num)| {
+            Some((
+                source..source.checked_add(num)?,
+                destination..destination.checked_add(num)?,
+            ))
+        },
+    )(input)
+}
+
+fn seed_map_p1(input: &str) -> IResult<&str, SeedMap<'_>> {
+    let (input, (source, destination)) = multispace1
+        .precedes(separated_pair(alpha1, tag("-to-"), alpha1))
+        .terminated(tag(" map:"))
+        .parse(input)?;
+    let (input, mappings) = many1(line_ending.precedes(line_p1))(input)?;
+
+    Ok((input, SeedMap { source, destination, mappings, passthrough: true }))
 }
 
 fn parse_seedmaps_p1(
     input: &str,
-) -> IResult<&str, (Vec<u64>, Vec<SeedMap>)> {
+) -> IResult<&str, (Vec<u64>, Vec<SeedMap<'_>>)> {
     let (input, seeds) = tag("seeds: ")
         .precedes(separated_list1(space1, complete::u64))
         .parse(input)?;
@@ -75,76 +468,61 @@ fn parse_seedmaps_p1(
     Ok((input, (seeds, maps)))
 }
 
-fn line_p2(
-    input: &str,
-) -> IResult<&str, (Range<u64>, Range<u64>)> {
-    let (input, (destination, source, num)) = tuple((
-        complete::u64,
-        complete::u64.preceded_by(tag(" ")),
-        complete::u64.preceded_by(tag(" ")),
-    ))(input)?;
-
-    Ok((
-        input,
-        (
-            source..(source + num),
-            destination..(destination + num),
-        ),
-    ))
-}
-
-fn seed_map_p2(input: &str) -> IResult<&str, SeedMap> {
-    take_until("map:")
-        .precedes(tag("map:"))
-        .precedes(
-            many1(line_ending.precedes(line_p2))
-                .map(|mappings| SeedMap { mappings }),
-        )
-        .parse(input)
-}
-
 fn parse_seedmaps_p2(
     input: &str,
-) -> IResult<&str, (Vec<Range<u64>>, Vec<SeedMap>)> {
+) -> IResult<&str, (Vec<Range<u64>>, Vec<SeedMap<'_>>)> {
     let (input, seeds) = tag("seeds: ")
         .precedes(separated_list1(
             space1,
-            separated_pair(complete::u64, tag(" "), complete::u64).map(|(start, offset)| {
-                start..(start+offset)
+            map_opt(separated_pair(complete::u64, tag(" "), complete::u64), |(start, offset)| {
+                Some(start..start.checked_add(offset)?)
             })))
         .parse(input)?;
-    let (input, maps) = many1(seed_map_p2)(input)?;
+    let (input, maps) = many1(seed_map_p1)(input)?;
 
     Ok((input, (seeds, maps)))
 }
 
+/// The lowest location the seed ranges reach, found with `strategy`, and the
+/// seeds that end up there.
+pub(crate) fn lowest(input: &str, strategy: Strategy) -> String {
+    let (_, (seeds, maps)) = parse_seedmaps_p2(input).expect(":)");
+    let almanac = Almanac::new(maps).unwrap_or_else(|e| panic!("{e}"));
+    let path = almanac.path("seed", "location").unwrap_or_else(|e| panic!("{e}"));
+
+    let lowest = almanac.lowest_location(&seeds, strategy).unwrap_or_else(|e| panic!("{e}"));
+    let Some(location) = lowest else {
+        return "no seeds to plant".to_string();
+    };
+    let origins = almanac
+        .invert_translate(&path, location)
+        .into_iter()
+        .filter(|seed| seeds.iter().any(|range| range.contains(seed)))
+        .join(", ");
+    format!("lowest location {location}, reached from seed {origins}")
+}
+
 impl Problem for DayFive {
     fn part_one(&self, input: &str) -> String {
         let (_, (seeds, maps)) = parse_seedmaps_p1(input).expect(":)");
+        let almanac = Almanac::new(maps).unwrap_or_else(|e| panic!("{e}"));
+        let path = almanac.path("seed", "location").unwrap_or_else(|e| panic!("{e}"));
 
         let locations = seeds
             .iter()
-            .map(|seed| {
-                maps.iter().fold(*seed, |seed, map| {
-                    map.translate(seed)
-                })
-            }).collect::<Vec<u64>>();
+            .map(|seed| almanac.translate(&path, *seed).unwrap_or_else(|e| panic!("{e}")))
+            .collect::<Vec<u64>>();
 
         locations.iter().min().expect("Should have minimum").to_string()
     }
 
     fn part_two(&self, input: &str) -> String {
         let (_, (seeds, maps)) = parse_seedmaps_p2(input).expect(":)");
+        let almanac = Almanac::new(maps).unwrap_or_else(|e| panic!("{e}"));
 
-        let minimum_location = seeds
-            .into_par_iter()
-            // .progress_count(count)
-            .flat_map(|range| range.clone())
-            .map(|seed| {
-                maps.iter()
-                    .fold(seed, |seed, map| map.translate(seed))
-            })
-            .min();
+        let minimum_location = almanac
+            .lowest_location(&seeds, Strategy::Backward)
+            .unwrap_or_else(|e| panic!("{e}"));
 
         minimum_location
             .expect("should have a minimum location value")
@@ -152,9 +530,46 @@ impl Problem for DayFive {
     }
 }
 
+#[cfg(test)]
 mod tests {
-    use crate::days::day05::day_five::DayFive;
+    use nom::combinator::all_consuming;
+
+    use crate::days::day05::day_five::{Almanac, AlmanacError, DayFive, Orientation, SeedMap, Strategy, line_p1, lowest, parse_seedmaps_p1, parse_seedmaps_p2, seed_map_p1};
     use crate::problem::Problem;
+    use crate::testing::{Rng, mutations, replace_one};
+
+    /// An almanac chaining seeds through a few random categories.
+    fn random_almanac(rng: &mut Rng) -> String {
+        let seeds = (0..rng.between(1, 6) * 2)
+            .map(|_| rng.below(1 << 32).to_string())
+            .collect::<Vec<String>>();
+        let mut categories = vec!["seed"];
+        for _ in 0..rng.between(1, 4) {
+            let next = *rng.pick(&["soil", "water", "light", "humidity", "location"]);
+            if !categories.contains(&next) {
+                categories.push(next);
+            }
+        }
+        let maps = categories
+            .windows(2)
+            .map(|pair| {
+                let lines = (0..rng.between(1, 5))
+                    .map(|_| format!("{} {} {}", rng.below(1 << 40), rng.below(1 << 40), rng.below(1 << 20)))
+                    .collect::<Vec<String>>();
+                format!("{}-to-{} map:\n{}", pair[0], pair[1], lines.join("\n"))
+            })
+            .collect::<Vec<String>>();
+        format!("seeds: {}\n\n{}", seeds.join(" "), maps.join("\n\n"))
+    }
+
+    fn print_map(map: &SeedMap) -> String {
+        let lines = map
+            .mappings
+            .iter()
+            .map(|(source, destination)| format!("{} {} {}", destination.start, source.start, source.end - source.start))
+            .collect::<Vec<String>>();
+        format!("\n\n{}-to-{} map:\n{}", map.source, map.destination, lines.join("\n"))
+    }
 
     #[test]
     fn part_one() {
@@ -231,4 +646,259 @@ humidity-to-location map:
 56 93 4";
         assert_eq!(DayFive.part_two(input), "46")
     }
+
+    #[test]
+    fn maps_out_of_order() {
+        let input = "seeds: 79 14 55 13
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+humidity-to-location map:
+60 56 37
+56 93 4
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+water-to-light map:
+88 18 7
+18 25 70
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13";
+        assert_eq!(DayFive.part_one(input), "35");
+        assert_eq!(DayFive.part_two(input), "46");
+    }
+
+    #[test]
+    fn category_paths() {
+        let input = "seeds: 79
+
+seed-to-soil map:
+50 98 2
+
+soil-to-fertilizer map:
+0 15 37
+
+fertilizer-to-water map:
+49 53 8";
+        let (_, (_, maps)) = parse_seedmaps_p1(input).unwrap();
+        let almanac = Almanac::new(maps).unwrap();
+
+        let forward = almanac.path("soil", "water").unwrap();
+        assert_eq!(forward.iter().map(|step| step.map).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(forward.iter().all(|step| step.orientation == Orientation::Forward));
+
+        let backward = almanac.path("water", "seed").unwrap();
+        assert_eq!(backward.iter().map(|step| step.map).collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert!(backward.iter().all(|step| step.orientation == Orientation::Backward));
+
+        assert_eq!(
+            almanac.path("seed", "location"),
+            Err(AlmanacError::UnknownCategory("location".to_string()))
+        );
+    }
+
+    #[test]
+    fn broken_chains() {
+        let input = "seeds: 79
+
+seed-to-soil map:
+50 98 2
+
+water-to-light map:
+88 18 7";
+        let (_, (_, maps)) = parse_seedmaps_p1(input).unwrap();
+        let almanac = Almanac::new(maps).unwrap();
+        assert_eq!(
+            almanac.path("seed", "light"),
+            Err(AlmanacError::NoPath { from: "seed".to_string(), to: "light".to_string() })
+        );
+
+        let input = "seeds: 79
+
+seed-to-soil map:
+50 98 2
+
+soil-to-water map:
+0 15 37
+
+water-to-seed map:
+88 18 7";
+        let (_, (_, maps)) = parse_seedmaps_p1(input).unwrap();
+        assert_eq!(
+            Almanac::new(maps).unwrap_err(),
+            AlmanacError::Cycle(vec![
+                "seed".to_string(),
+                "soil".to_string(),
+                "water".to_string(),
+                "seed".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn inverse_lookup() {
+        let input = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4";
+        let (_, (_, maps)) = parse_seedmaps_p1(input).unwrap();
+        let almanac = Almanac::new(maps).unwrap();
+        let path = almanac.path("seed", "water").unwrap();
+
+        for seed in 0..200 {
+            let water = almanac.translate(&path, seed).unwrap();
+            assert!(almanac.invert_translate(&path, water).contains(&seed));
+        }
+        assert_eq!(almanac.follow(&almanac.path("water", "seed").unwrap(), 81), vec![79]);
+        assert_eq!(
+            almanac.translate(&almanac.path("water", "seed").unwrap(), 81),
+            Err(AlmanacError::BackwardStep { source: "fertilizer".to_string(), destination: "water".to_string() })
+        );
+    }
+
+    #[test]
+    fn inverse_passthrough() {
+        let input = "seeds: 79
+
+seed-to-soil map:
+0 10 5";
+        let (_, (_, maps)) = parse_seedmaps_p1(input).unwrap();
+        let almanac = Almanac::new(maps).unwrap();
+        let path = almanac.path("seed", "soil").unwrap();
+
+        // 3 is reached both through the mapping and by passing through
+        assert_eq!(almanac.invert_translate(&path, 3), vec![3, 13]);
+        // 12 is mapped away and nothing maps onto it
+        assert_eq!(almanac.invert_translate(&path, 12), Vec::<u64>::new());
+        // 20 is only passed through
+        assert_eq!(almanac.invert_translate(&path, 20), vec![20]);
+    }
+
+    #[test]
+    fn backward_strategy_agrees() {
+        let input = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4";
+        let (_, (seeds, maps)) = parse_seedmaps_p2(input).unwrap();
+        let almanac = Almanac::new(maps).unwrap();
+
+        assert_eq!(almanac.lowest_location(&seeds, Strategy::Forward), Ok(Some(46)));
+        assert_eq!(almanac.lowest_location(&seeds, Strategy::Backward), Ok(Some(46)));
+        assert_eq!(lowest(input, Strategy::Backward), "lowest location 46, reached from seed 82");
+        assert_eq!(almanac.lowest_location(&[], Strategy::Backward), Ok(None));
+        for seed in 0..100 {
+            let range = seed..seed + 1;
+            let seeds = std::slice::from_ref(&range);
+            assert_eq!(
+                almanac.lowest_location(seeds, Strategy::Forward),
+                almanac.lowest_location(seeds, Strategy::Backward),
+            );
+        }
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(5);
+        for _ in 0..200 {
+            let input = random_almanac(&mut rng);
+            let (_, (seeds, maps)) = all_consuming(parse_seedmaps_p1)(&input).unwrap();
+            let printed = format!(
+                "seeds: {}{}",
+                seeds.iter().map(u64::to_string).collect::<Vec<String>>().join(" "),
+                maps.iter().map(print_map).collect::<String>()
+            );
+            assert_eq!(printed, input);
+            assert_eq!(all_consuming(parse_seedmaps_p1)(&printed).unwrap().1, (seeds, maps));
+
+            let line = input.lines().nth(3).unwrap();
+            let (_, mapping) = all_consuming(line_p1)(line).unwrap();
+            assert_eq!(mapping.0.end - mapping.0.start, mapping.1.end - mapping.1.start);
+            let map = &input[input.find("\n\n").unwrap()..];
+            assert!(seed_map_p1(map).is_ok());
+        }
+    }
+
+    #[test]
+    fn mutated_almanacs_fail_cleanly() {
+        let mut rng = Rng::new(5);
+        for _ in 0..200 {
+            let input = random_almanac(&mut rng);
+            for mutated in mutations(&mut rng, &input) {
+                let _ = parse_seedmaps_p2(&mutated);
+                let parsed = all_consuming(parse_seedmaps_p1)(&mutated);
+                if mutated.contains(|c: char| !c.is_ascii_alphanumeric() && !" :-\n".contains(c)) {
+                    assert!(parsed.is_err(), "{mutated}");
+                }
+            }
+
+            let broken = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "#").unwrap();
+            assert!(all_consuming(parse_seedmaps_p1)(&broken).is_err(), "{broken}");
+            let overflowing = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "99999999999999999999").unwrap();
+            assert!(all_consuming(parse_seedmaps_p1)(&overflowing).is_err(), "{overflowing}");
+        }
+
+        // Ranges that would end past u64::MAX
+        assert!(line_p1("0 18446744073709551615 1").is_err());
+        assert!(parse_seedmaps_p2("seeds: 18446744073709551615 2\n\nseed-to-soil map:\n0 0 1").is_err());
+    }
 }
\ No newline at end of file
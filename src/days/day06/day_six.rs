@@ -2,10 +2,12 @@ use nom::{IResult, Parser};
 use nom::bytes::streaming::is_not;
 use nom::character::complete;
 use nom::character::complete::{digit1, line_ending, space1};
+use nom::combinator::map_res;
 use nom::multi::separated_list1;
 use nom::sequence::separated_pair;
 use nom_supreme::ParserExt;
 
+use crate::math;
 use crate::problem::Problem;
 
 pub(crate) struct DaySix;
@@ -21,10 +23,10 @@ fn parse_p1(input: &str) -> IResult<&str, (Vec<u32>, Vec<u32>)> {
 }
 
 fn parse_numbers_p2(input: &str) -> IResult<&str, u64> {
-    is_not("0123456789")
-        .precedes(separated_list1(space1, digit1)).map(|list|{
-            list.join("").parse::<u64>().expect(":)")
-        },
+    // Too many digits for a u64 is a parse error rather than a panic
+    map_res(
+        is_not("0123456789").precedes(separated_list1(space1, digit1)),
+        |list| list.join("").parse::<u64>(),
     )
         .parse(input)
 }
@@ -41,12 +43,8 @@ impl Problem for DaySix {
             .iter()
             .zip(distances)
             .map(|(time, record_distance)| {
-                (0..*time)
-                    .filter_map(|speed| {
-                        let distance = (time - speed) * speed;
-                        (distance > record_distance).then_some(distance)
-                    }).count()
-            }).product::<usize>();
+                math::winning_hold_times(*time as u64, record_distance as u64)
+            }).product::<u64>();
 
         result.to_string()
     }
@@ -54,22 +52,54 @@ impl Problem for DaySix {
     fn part_two(&self, input: &str) -> String {
         let (_, (time, curr_record)) = parse_p2(input).expect("Must parse");
 
-        let result =
-                (0..time)
-                    .filter_map(|speed| {
-                        let distance = (time - speed) * speed;
-                        (distance > curr_record).then_some(distance)
-                    }).count();
+        let result = math::winning_hold_times(time, curr_record);
 
         result.to_string()
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use nom::combinator::all_consuming;
+
     use crate::problem::Problem;
+    use crate::testing::{Rng, mutations, replace_one};
 
     use super::*;
 
+    /// Race sheets with the numbers right-aligned in columns, as the puzzle
+    /// prints them.
+    fn random_races(rng: &mut Rng) -> String {
+        let races = (0..rng.between(1, 5))
+            .map(|_| (rng.below(100), rng.below(2_000)))
+            .collect::<Vec<(u64, u64)>>();
+        let width = rng.between(5, 8);
+        let row = |numbers: Vec<u64>| numbers.iter().map(|number| format!("{number:>width$}")).collect::<String>();
+        format!(
+            "Time:    {}\nDistance:{}",
+            row(races.iter().map(|race| race.0).collect()),
+            row(races.iter().map(|race| race.1).collect())
+        )
+    }
+
+    /// Whether the digit at `index` comes after another digit on its line.
+    /// The first number's leading digit can be swapped for anything, as the
+    /// label in front of it is skipped.
+    fn follows_a_digit(input: &str, index: usize) -> bool {
+        input[..index]
+            .rsplit('\n')
+            .next()
+            .is_some_and(|line| line.contains(|c: char| c.is_ascii_digit()))
+    }
+
+    fn winning_hold_times_iterative(time: u64, record: u64) -> u64 {
+        (0..time)
+            .filter_map(|speed| {
+                let distance = (time - speed) * speed;
+                (distance > record).then_some(distance)
+            }).count() as u64
+    }
+
     #[test]
     fn part_one() {
         let input = "Time:      7  15   30
@@ -83,4 +113,67 @@ Distance:  9  40  200";
 Distance:  9  40  200";
         assert_eq!(DaySix.part_two(input), "71503")
     }
+
+    #[test]
+    fn closed_form_matches_iteration() {
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2_000 {
+            let time = rng.below(2_000);
+            let best = (time / 2) * (time - time / 2);
+            // Aim around the best distance so ties and empty races show up
+            let record = match rng.below(4) {
+                0 => best,
+                1 => best.saturating_sub(1),
+                _ => rng.below(best + 1),
+            };
+            assert_eq!(
+                math::winning_hold_times(time, record),
+                winning_hold_times_iterative(time, record),
+                "time {time}, record {record}"
+            );
+        }
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(6);
+        for _ in 0..200 {
+            let input = random_races(&mut rng);
+            let (_, (times, distances)) = all_consuming(parse_p1)(&input).unwrap();
+            let join = |numbers: &[u32]| numbers.iter().map(u32::to_string).collect::<Vec<String>>().join(" ");
+            let printed = format!("Time: {}\nDistance: {}", join(&times), join(&distances));
+            assert_eq!(all_consuming(parse_p1)(&printed).unwrap().1, (times, distances));
+
+            let (_, (time, distance)) = all_consuming(parse_p2)(&input).unwrap();
+            let printed = format!("Time: {time}\nDistance: {distance}");
+            assert_eq!(all_consuming(parse_p2)(&printed).unwrap().1, (time, distance));
+        }
+    }
+
+    #[test]
+    fn mutated_races_fail_cleanly() {
+        let mut rng = Rng::new(6);
+        for _ in 0..200 {
+            let input = random_races(&mut rng);
+            for mutated in mutations(&mut rng, &input) {
+                let (p1, p2) = (all_consuming(parse_p1)(&mutated), all_consuming(parse_p2)(&mutated));
+                // Labels may hold anything, the numbers after them may not
+                let foreign = mutated
+                    .char_indices()
+                    .any(|(index, c)| !c.is_ascii_digit() && !" \n".contains(c) && follows_a_digit(&mutated, index));
+                if foreign {
+                    assert!(p1.is_err() && p2.is_err(), "{mutated}");
+                }
+            }
+
+            let eligible = |index, c: char| c.is_ascii_digit() && follows_a_digit(&input, index);
+            if let Some(broken) = replace_one(&mut rng, &input, eligible, "#") {
+                assert!(all_consuming(parse_p1)(&broken).is_err(), "{broken}");
+                assert!(all_consuming(parse_p2)(&broken).is_err(), "{broken}");
+            }
+            let overflowing = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "99999999999999999999").unwrap();
+            assert!(all_consuming(parse_p1)(&overflowing).is_err(), "{overflowing}");
+            assert!(all_consuming(parse_p2)(&overflowing).is_err(), "{overflowing}");
+        }
+    }
 }
\ No newline at end of file
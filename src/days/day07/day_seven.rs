@@ -1,158 +1,398 @@
-use std::ops::Deref;
+use std::fmt;
 
-use itertools::{Itertools, Position};
+use itertools::Itertools;
 
 use crate::problem::Problem;
 
 pub(crate) struct DaySeven;
 
-#[derive(Debug, Clone, Copy)]
-enum HandType {
-    FiveOfAKind = 6,
-    FourOfAKind = 5,
-    FullHouse = 4,
-    ThreeOfAKind = 3,
-    TwoPair = 2,
-    OnePair = 1,
-    HighCard = 0,
-}
-
-fn score_hand(
-    hand: &str,
-) -> (HandType, (u32, u32, u32, u32, u32)) {
-    use HandType::*;
-
-    let counts = hand.chars().counts();
-    let values = counts.values().sorted().join("");
-    let hand_type = match values.deref() {
-        "5" => FiveOfAKind,
-        "14" => FourOfAKind,
-        "23" => FullHouse,
-        "113" => ThreeOfAKind,
-        "122" => TwoPair,
-        "1112" => OnePair,
-        "11111" => HighCard,
-        value => panic!(
-            "should never happen. Encountered `{}`",
-            value
-        ),
-    };
-    let card_scores = hand
-        .chars()
-        .map(|card| match card {
-            'A' => 14,
-            'K' => 13,
-            'Q' => 12,
-            'J' => 11,
-            'T' => 10,
-            value => value.to_digit(10).unwrap(),
-        })
-        .collect_tuple()
-        .unwrap();
-    (hand_type, card_scores)
-}
-
-fn score_hand_p2(
-    hand: &str,
-) -> (HandType, (u32, u32, u32, u32, u32)) {
-    use HandType::*;
-
-    let counts = hand.chars().counts();
-
-    let values = if let Some(joker_count) = counts.get(&'J')
-    {
-        if *joker_count == 5 {
-            "5".to_string()
-        } else {
-            counts
-                .iter()
-                .filter_map(|(key, value)| {
-                    (key != &'J').then_some(value)
-                })
-                .sorted()
-                .with_position()
-                .map(|(position, value)| match position {
-                    Position::Last | Position::Only => {
-                        value + joker_count
-                    }
-                    _ => *value,
-                })
-                .join("")
+/// The category a hand falls into. Stronger categories compare greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct HandType {
+    /// Position in the ruleset's category table.
+    strength: usize,
+    name: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    /// The number of cards of each rank, largest first, e.g. `[3, 2]` for a
+    /// full house.
+    Counts(Vec<usize>),
+    /// Every card has the same suit.
+    Flush,
+}
+
+#[derive(Debug, Clone)]
+struct Category {
+    name: &'static str,
+    pattern: Pattern,
+}
+
+impl Category {
+    fn counts(name: &'static str, counts: &[usize]) -> Self {
+        Category { name, pattern: Pattern::Counts(counts.to_vec()) }
+    }
+
+    fn flush(name: &'static str) -> Self {
+        Category { name, pattern: Pattern::Flush }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Card {
+    rank: char,
+    suit: Option<char>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum HandError {
+    UnknownCard(char),
+    UnknownSuit(char),
+    MissingSuit(char),
+    WrongSize { hand: String, expected: usize, found: usize },
+    Unclassified(String),
+    MalformedLine(String),
+}
+
+impl fmt::Display for HandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandError::UnknownCard(card) => write!(f, "`{card}` is not a card in this ruleset"),
+            HandError::UnknownSuit(suit) => write!(f, "`{suit}` is not a suit in this ruleset"),
+            HandError::MissingSuit(card) => write!(f, "card `{card}` has no suit"),
+            HandError::WrongSize { hand, expected, found } => {
+                write!(f, "hand `{hand}` has {found} cards, expected {expected}")
+            }
+            HandError::Unclassified(hand) => write!(f, "hand `{hand}` matches no category"),
+            HandError::MalformedLine(line) => write!(f, "expected `<hand> <bid>`, found `{line}`"),
         }
-    } else {
-        counts.values().sorted().join("")
-    };
+    }
+}
 
-    let hand_type = match values.deref() {
-        "5" => FiveOfAKind,
-        "14" => FourOfAKind,
-        "23" => FullHouse,
-        "113" => ThreeOfAKind,
-        "122" => TwoPair,
-        "1112" => OnePair,
-        "11111" => HighCard,
-        value => panic!(
-            "should never happen. Encountered `{}`",
-            value
-        ),
-    };
-    let card_scores = hand
-        .chars()
-        .map(|card| match card {
-            'A' => 14,
-            'K' => 13,
-            'Q' => 12,
-            'J' => 1,
-            'T' => 10,
-            value => value.to_digit(10).unwrap(),
-        })
-        .collect_tuple()
-        .unwrap();
-    (hand_type, card_scores)
+/// A hand scored under some ruleset.
+#[derive(Debug, Clone)]
+struct ScoredHand {
+    cards: Vec<Card>,
+    hand_type: HandType,
+    /// The rank each wildcard stood in for, in the order they appear.
+    substitution: Vec<char>,
+    /// Card strengths in hand order, used to break ties between hands of the
+    /// same type.
+    tie_break: Vec<usize>,
 }
 
-impl Problem for DaySeven {
-    fn part_one(
-        &self,
-        input: &str,
-    ) -> String {
-        let hands = input
-            .lines()
-            .map(|line| {
-                let (hand, bid) = line.split_once(" ").unwrap();
-                (
-                    hand,
-                    bid.parse::<u32>().unwrap(),
-                    score_hand(hand),
-                )
-            })
-            .sorted_by_key(|x| (x.2.0 as u8, x.2.1))
+/// Everything that differs between variants of Camel Cards.
+#[derive(Debug, Clone)]
+struct Ruleset {
+    /// Card ranks, weakest first.
+    order: Vec<char>,
+    /// When set, every card is written as a rank followed by one of these
+    /// suits.
+    suits: Option<Vec<char>>,
+    /// A rank that stands in for whichever rank makes the strongest hand.
+    /// Its own place in `order` is used to break ties.
+    wildcard: Option<char>,
+    hand_size: usize,
+    /// Weakest first. A hand takes the strongest category it matches.
+    categories: Vec<Category>,
+}
+
+impl Ruleset {
+    fn standard_categories() -> Vec<Category> {
+        vec![
+            Category::counts("high card", &[1, 1, 1, 1, 1]),
+            Category::counts("one pair", &[2, 1, 1, 1]),
+            Category::counts("two pair", &[2, 2, 1]),
+            Category::counts("three of a kind", &[3, 1, 1]),
+            Category::counts("full house", &[3, 2]),
+            Category::counts("four of a kind", &[4, 1]),
+            Category::counts("five of a kind", &[5]),
+        ]
+    }
+
+    /// The rules of part one.
+    fn standard() -> Self {
+        Ruleset {
+            order: "23456789TJQKA".chars().collect(),
+            suits: None,
+            wildcard: None,
+            hand_size: 5,
+            categories: Self::standard_categories(),
+        }
+    }
+
+    /// The rules of part two, where `J` is a joker.
+    fn jokers() -> Self {
+        Ruleset {
+            order: "J23456789TQKA".chars().collect(),
+            wildcard: Some('J'),
+            ..Self::standard()
+        }
+    }
+
+    /// These rules with every card followed by one of `suits`. A flush ranks
+    /// just below a full house, as in poker.
+    fn with_suits(self, suits: &str) -> Self {
+        let mut categories = self.categories;
+        let full_house = categories
+            .iter()
+            .position(|category| category.name == "full house")
+            .unwrap_or(categories.len());
+        categories.insert(full_house, Category::flush("flush"));
+        Ruleset { suits: Some(suits.chars().collect()), categories, ..self }
+    }
+
+    fn parse_cards(&self, hand: &str) -> Result<Vec<Card>, HandError> {
+        let mut chars = hand.chars();
+        let mut cards = vec![];
+        while let Some(rank) = chars.next() {
+            if !self.order.contains(&rank) {
+                return Err(HandError::UnknownCard(rank));
+            }
+            let suit = match &self.suits {
+                Some(suits) => {
+                    let suit = chars.next().ok_or(HandError::MissingSuit(rank))?;
+                    if !suits.contains(&suit) {
+                        return Err(HandError::UnknownSuit(suit));
+                    }
+                    Some(suit)
+                }
+                None => None,
+            };
+            cards.push(Card { rank, suit });
+        }
+
+        if cards.len() != self.hand_size {
+            return Err(HandError::WrongSize {
+                hand: hand.to_string(),
+                expected: self.hand_size,
+                found: cards.len(),
+            });
+        }
+        Ok(cards)
+    }
+
+    fn classify(&self, counts: &[usize], flush: bool) -> Option<HandType> {
+        self.categories
+            .iter()
             .enumerate()
-            .map(|(index, (_hand, bid, _))| {
-                (index as u32 + 1) * bid
+            .rev()
+            .find(|(_, category)| match &category.pattern {
+                Pattern::Counts(pattern) => pattern == counts,
+                Pattern::Flush => flush,
             })
-            .sum::<u32>();
-        hands.to_string()
+            .map(|(strength, category)| HandType { strength, name: category.name })
     }
 
-    fn part_two(&self, input: &str) -> String {
+    fn score(&self, hand: &str) -> Result<ScoredHand, HandError> {
+        let cards = self.parse_cards(hand)?;
+        let is_wild = |card: &Card| Some(card.rank) == self.wildcard;
+
+        let fixed = cards.iter().filter(|card| !is_wild(card)).collect::<Vec<&Card>>();
+        let wild_count = cards.len() - fixed.len();
+        // Wildcards take on whatever suit the other cards share
+        let flush = self.suits.is_some()
+            && fixed.iter().map(|card| card.suit).all_equal();
+
+        let (hand_type, substitution) = self
+            .order
+            .iter()
+            .filter(|rank| Some(**rank) != self.wildcard)
+            .copied()
+            .combinations_with_replacement(wild_count)
+            .filter_map(|substitution| {
+                let counts = fixed
+                    .iter()
+                    .map(|card| card.rank)
+                    .chain(substitution.iter().copied())
+                    .counts()
+                    .into_values()
+                    .sorted()
+                    .rev()
+                    .collect::<Vec<usize>>();
+                self.classify(&counts, flush)
+                    .map(|hand_type| (hand_type, substitution))
+            })
+            .max_by_key(|(hand_type, _)| *hand_type)
+            .ok_or_else(|| HandError::Unclassified(hand.to_string()))?;
+
+        let tie_break = cards
+            .iter()
+            .map(|card| {
+                self.order
+                    .iter()
+                    .position(|rank| *rank == card.rank)
+                    .expect("cards are checked against the order")
+            })
+            .collect();
+
+        Ok(ScoredHand { cards, hand_type, substitution, tie_break })
+    }
+
+    /// The hand as it plays, with every wildcard replaced by the rank it
+    /// stood in for.
+    fn substituted(&self, hand: &ScoredHand) -> String {
+        let mut substitution = hand.substitution.iter();
+        hand.cards
+            .iter()
+            .flat_map(|card| {
+                let rank = if Some(card.rank) == self.wildcard {
+                    *substitution.next().expect("one substitution per wildcard")
+                } else {
+                    card.rank
+                };
+                [Some(rank), card.suit].into_iter().flatten()
+            })
+            .collect()
+    }
+
+    /// Scores every `<hand> <bid>` line and orders them from weakest to
+    /// strongest.
+    fn rank<'a>(&self, input: &'a str) -> Result<Vec<RankedHand<'a>>, HandError> {
         let hands = input
             .lines()
             .map(|line| {
-                let (hand, bid) = line.split_once(" ").unwrap();
-                (
-                    hand,
-                    bid.parse::<u32>().unwrap(),
-                    score_hand_p2(hand),
-                )
+                let (hand, bid) = line
+                    .split_once(' ')
+                    .ok_or_else(|| HandError::MalformedLine(line.to_string()))?;
+                let bid = bid
+                    .parse::<u32>()
+                    .map_err(|_| HandError::MalformedLine(line.to_string()))?;
+                Ok((hand, bid, self.score(hand)?))
+            })
+            .collect::<Result<Vec<(&str, u32, ScoredHand)>, HandError>>()?;
+
+        Ok(hands
+            .into_iter()
+            .sorted_by(|(_, _, a), (_, _, b)| {
+                (a.hand_type, &a.tie_break).cmp(&(b.hand_type, &b.tie_break))
             })
-            .sorted_by_key(|x| (x.2.0 as u8, x.2.1))
             .enumerate()
-            .map(|(index, (_hand, bid, _))| {
-                (index as u32 + 1) * bid
+            .map(|(index, (hand, bid, scored))| RankedHand {
+                hand,
+                bid,
+                scored,
+                rank: index as u32 + 1,
             })
-            .sum::<u32>();
-        hands.to_string()
+            .collect())
+    }
+
+    fn total_winnings(&self, input: &str) -> Result<u32, HandError> {
+        Ok(self
+            .rank(input)?
+            .iter()
+            .map(RankedHand::winnings)
+            .sum::<u32>())
+    }
+}
+
+#[derive(Debug)]
+struct RankedHand<'a> {
+    hand: &'a str,
+    bid: u32,
+    scored: ScoredHand,
+    /// 1 for the weakest hand.
+    rank: u32,
+}
+
+impl<'a> RankedHand<'a> {
+    fn winnings(&self) -> u32 {
+        self.rank * self.bid
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReportFormat {
+    Table,
+    Json,
+}
+
+/// Lists every hand with the type it got, what its wildcards became and
+/// what it contributed to the total winnings, ordered by rank. `None` when
+/// there is no such part.
+pub(crate) fn report(input: &str, part: u8, suits: Option<&str>, format: ReportFormat) -> Option<String> {
+    let ruleset = match part {
+        1 => Ruleset::standard(),
+        2 => Ruleset::jokers(),
+        _ => return None,
+    };
+    let ruleset = match suits {
+        Some(suits) => ruleset.with_suits(suits),
+        None => ruleset,
+    };
+    let hands = ruleset.rank(input).unwrap_or_else(|e| panic!("{e}"));
+    let total = hands.iter().map(RankedHand::winnings).sum::<u32>();
+
+    Some(match format {
+        ReportFormat::Table => {
+            let width = hands
+                .iter()
+                .map(|hand| hand.hand.len())
+                .max()
+                .unwrap_or(0)
+                .max("hand".len());
+            let plays_as_width = width.max("plays as".len());
+            let mut lines = vec![format!(
+                "{:>5}  {:<width$}  {:<plays_as_width$}  {:<15}  {:>6}  {:>9}",
+                "rank", "hand", "plays as", "type", "bid", "winnings"
+            )];
+            for hand in &hands {
+                let plays_as = if hand.scored.substitution.is_empty() {
+                    "-".to_string()
+                } else {
+                    ruleset.substituted(&hand.scored)
+                };
+                lines.push(format!(
+                    "{:>5}  {:<width$}  {:<plays_as_width$}  {:<15}  {:>6}  {:>9}",
+                    hand.rank,
+                    hand.hand,
+                    plays_as,
+                    hand.scored.hand_type.name,
+                    hand.bid,
+                    hand.winnings()
+                ));
+            }
+            lines.push(format!("total winnings: {total}"));
+            lines.join("\n")
+        }
+        ReportFormat::Json => {
+            // Hands only contain ruleset characters, so nothing needs escaping
+            let hands = hands
+                .iter()
+                .map(|hand| {
+                    format!(
+                        "{{\"rank\":{},\"hand\":\"{}\",\"plays_as\":\"{}\",\"type\":\"{}\",\"bid\":{},\"winnings\":{}}}",
+                        hand.rank,
+                        hand.hand,
+                        ruleset.substituted(&hand.scored),
+                        hand.scored.hand_type.name,
+                        hand.bid,
+                        hand.winnings()
+                    )
+                })
+                .join(",");
+            format!("{{\"hands\":[{hands}],\"total\":{total}}}")
+        }
+    })
+}
+
+impl Problem for DaySeven {
+    fn part_one(
+        &self,
+        input: &str,
+    ) -> String {
+        Ruleset::standard()
+            .total_winnings(input)
+            .unwrap_or_else(|e| panic!("{e}"))
+            .to_string()
+    }
+
+    fn part_two(&self, input: &str) -> String {
+        Ruleset::jokers()
+            .total_winnings(input)
+            .unwrap_or_else(|e| panic!("{e}"))
+            .to_string()
     }
 }
 
@@ -180,4 +420,94 @@ KTJJT 220
 QQQJA 483";
         assert_eq!(DaySeven.part_two(input), "5905");
     }
+
+    #[test]
+    fn joker_substitution() {
+        let ruleset = Ruleset::jokers();
+
+        let hand = ruleset.score("KTJJT").unwrap();
+        assert_eq!(hand.hand_type.name, "four of a kind");
+        assert_eq!(hand.substitution, vec!['T', 'T']);
+
+        let hand = ruleset.score("JJJJJ").unwrap();
+        assert_eq!(hand.hand_type.name, "five of a kind");
+        assert_eq!(hand.tie_break, vec![0; 5]);
+
+        assert_eq!(ruleset.score("32T3K").unwrap().hand_type.name, "one pair");
+        assert_eq!(ruleset.score("32T3X").unwrap_err(), HandError::UnknownCard('X'));
+        assert!(matches!(ruleset.score("32T3"), Err(HandError::WrongSize { found: 4, .. })));
+    }
+
+    #[test]
+    fn six_card_hands() {
+        let ruleset = Ruleset {
+            hand_size: 6,
+            categories: vec![
+                Category::counts("high card", &[1, 1, 1, 1, 1, 1]),
+                Category::counts("one pair", &[2, 1, 1, 1, 1]),
+                Category::counts("two pair", &[2, 2, 1, 1]),
+                Category::counts("three pair", &[2, 2, 2]),
+                Category::counts("three of a kind", &[3, 1, 1, 1]),
+                Category::counts("full house", &[3, 2, 1]),
+                Category::counts("two triples", &[3, 3]),
+                Category::counts("four of a kind", &[4, 1, 1]),
+                Category::counts("four and a pair", &[4, 2]),
+                Category::counts("five of a kind", &[5, 1]),
+                Category::counts("six of a kind", &[6]),
+            ],
+            ..Ruleset::jokers()
+        };
+
+        assert_eq!(ruleset.score("223344").unwrap().hand_type.name, "three pair");
+        assert_eq!(ruleset.score("22J344").unwrap().hand_type.name, "full house");
+        assert_eq!(ruleset.score("222JJJ").unwrap().hand_type.name, "six of a kind");
+        assert!(ruleset.score("22J344").unwrap().hand_type > ruleset.score("223344").unwrap().hand_type);
+    }
+
+    #[test]
+    fn flushes() {
+        let ruleset = Ruleset::jokers().with_suits("cdhs");
+
+        let flush = ruleset.score("2h5h9hJcKh").unwrap();
+        assert_eq!(flush.hand_type.name, "flush");
+        assert!(flush.hand_type > ruleset.score("2c2d2h5s9c").unwrap().hand_type);
+        assert!(flush.hand_type < ruleset.score("2c2d2h5s5c").unwrap().hand_type);
+        assert_eq!(ruleset.score("2h5h9hJcKs").unwrap().hand_type.name, "one pair");
+        assert_eq!(ruleset.score("2h5x9hJcKs").unwrap_err(), HandError::UnknownSuit('x'));
+
+        let input = "2c2d2h5s9c 1
+2h5h9hJcKh 10";
+        assert_eq!(ruleset.total_winnings(input), Ok(21));
+        assert!(report(input, 2, Some("cdhs"), ReportFormat::Json).unwrap().contains(r#""type":"flush""#));
+    }
+
+    #[test]
+    fn report_table() {
+        let input = "32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483";
+        assert_eq!(
+            report(input, 2, None, ReportFormat::Table).unwrap(),
+            " rank  hand   plays as  type                bid   winnings
+    1  32T3K  -         one pair            765        765
+    2  KK677  -         two pair             28         56
+    3  T55J5  T5555     four of a kind      684       2052
+    4  QQQJA  QQQQA     four of a kind      483       1932
+    5  KTJJT  KTTTT     four of a kind      220       1100
+total winnings: 5905"
+        );
+    }
+
+    #[test]
+    fn report_json() {
+        let input = "32T3K 765
+KTJJT 220";
+        assert_eq!(
+            report(input, 1, None, ReportFormat::Json).unwrap(),
+            r#"{"hands":[{"rank":1,"hand":"32T3K","plays_as":"32T3K","type":"one pair","bid":765,"winnings":765},{"rank":2,"hand":"KTJJT","plays_as":"KTJJT","type":"two pair","bid":220,"winnings":440}],"total":1205}"#
+        );
+        assert_eq!(report(input, 3, None, ReportFormat::Json), None);
+    }
 }
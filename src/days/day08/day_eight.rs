@@ -1,4 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use itertools::Itertools;
 
 use nom::{IResult, Parser};
 use nom::branch::alt;
@@ -8,8 +11,10 @@ use nom::character::complete::{multispace1, space1};
 use nom::combinator::opt;
 use nom::error::Error;
 use nom::multi::many1;
-use nom::sequence::{preceded, separated_pair};
+use nom::sequence::{delimited, preceded, separated_pair};
 
+use crate::graph::{DotOptions, Graph};
+use crate::math;
 use crate::problem::Problem;
 
 pub struct DayEight;
@@ -28,7 +33,7 @@ fn parse_nodes(input: &str) -> IResult<&str, (&str, &str)> {
     separated_pair(
         preceded(tag("("), take_until(",")),
         tag(","),
-        preceded(space1, take_until(")")),
+        delimited(space1, take_until(")"), tag(")")),
     )(input)
 }
 
@@ -58,34 +63,9 @@ impl Problem for DayEight {
                 acc
             });
 
-        let mut current_node = "AAA";
-        const DESTINATION: &str = "ZZZ";
-        let Some(step_count) = instructions
-            .iter()
-            .cycle()
-            .enumerate()
-            .find_map(|(i, direction)| {
-                let nodes = tree
-                    .get(current_node)
-                    .expect("Must be a valid node");
-
-                let next_node = match direction {
-                    Direction::LEFT => nodes.0,
-                    Direction::RIGHT => nodes.1,
-                };
-
-                if next_node == DESTINATION {
-                    Some(i + 1)
-                } else {
-                    current_node = next_node;
-                    None
-                }
-            })
-        else {
-            panic!("Infinite iterator")
-        };
-
-        step_count.to_string()
+        steps_between(&tree, &instructions, "AAA", "ZZZ")
+            .unwrap_or_else(|e| panic!("{e}"))
+            .to_string()
     }
 
     fn part_two(&self, input: &str) -> String {
@@ -101,65 +81,299 @@ impl Problem for DayEight {
                 acc
             });
 
-        let current_nodes: Vec<&str> = tree
+        let cycles = tree
             .keys()
-            .filter(|key| key.ends_with("A"))
-            .cloned()
-            .collect();
+            .filter(|key| key.ends_with('A'))
+            .map(|start| ghost_cycle(&tree, &instructions, start))
+            .collect::<Result<Vec<GhostCycle>, WalkError>>()
+            .unwrap_or_else(|e| panic!("{e}"));
 
-        let results = current_nodes
-            .iter()
-            .map(|node| {
-                let mut current_node = *node;
-
-                instructions
-                    .iter()
-                    .cycle()
-                    .enumerate()
-                    .find_map(|(i, direction)| {
-                        let nodes = tree
-                            .get(current_node)
-                            .expect("Must be a valid node");
-
-                        let next_node = match direction {
-                            Direction::LEFT => nodes.0,
-                            Direction::RIGHT => nodes.1,
-                        };
-
-                        if next_node.ends_with('Z') {
-                            Some(i + 1)
-                        } else {
-                            current_node = next_node;
-                            None
-                        }
-                    }).expect(":)")
-            })
-            .collect::<Vec<usize>>();
+        earliest_common_hit(&cycles)
+            .unwrap_or_else(|e| panic!("{e}"))
+            .to_string()
+    }
+}
+
+/// Renders the network in Graphviz DOT format. Nodes ending with `A` are
+/// filled green, nodes ending with `Z` red, and the loop each ghost ends up
+/// walking gets its own colour.
+pub(crate) fn to_dot(input: &str, max_nodes: Option<usize>) -> String {
+    const GHOST_COLOURS: [&str; 6] = ["blue", "darkorange", "purple", "brown", "deeppink", "cyan4"];
+
+    let (input, instructions) = parse_instruction(input).expect(":)");
+    let tree: BTreeMap<&str, (&str, &str)> = input
+        .lines()
+        .filter_map(|line| parse_tree(line).ok().map(|(_, node)| node))
+        .collect();
+
+    let graph = network_graph(&tree);
+    let mut options = DotOptions { max_nodes, ..DotOptions::default() };
+    for (index, node) in (0..graph.len()).map(|index| (index, *graph.node(index))) {
+        if node.ends_with('A') {
+            options.node_attributes.insert(index, "style=filled, fillcolor=palegreen".to_string());
+            options.roots.push(index);
+        } else if node.ends_with('Z') {
+            options.node_attributes.insert(index, "style=filled, fillcolor=salmon".to_string());
+        }
+    }
+
+    let starts = tree.keys().filter(|key| key.ends_with('A'));
+    for (start, colour) in starts.zip(GHOST_COLOURS.iter().cycle()) {
+        // Ghosts walking into a missing node have no loop to draw
+        let Ok(cycle) = ghost_cycle(&tree, &instructions, start) else {
+            continue;
+        };
+        let mut node = *start;
+        for step in 0..cycle.offset + cycle.length {
+            let (left, right) = tree[node];
+            let next = match instructions[step % instructions.len()] {
+                Direction::LEFT => left,
+                Direction::RIGHT => right,
+            };
+            if step >= cycle.offset {
+                let (from, to) = (graph.index_of(&node), graph.index_of(&next));
+                let (from, to) = (from.expect("walked nodes are in the graph"), to.expect("walked nodes are in the graph"));
+                options.edge_attributes.insert((from, to), format!("color={colour}, penwidth=2"));
+                let attributes = options.node_attributes.entry(from).or_default();
+                if !attributes.contains("penwidth") {
+                    if !attributes.is_empty() {
+                        attributes.push_str(", ");
+                    }
+                    attributes.push_str(&format!("color={colour}, penwidth=2"));
+                }
+            }
+            node = next;
+        }
+    }
+
+    graph.to_dot(&options)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum WalkError {
+    MissingNode(String),
+    DanglingEdge { from: String, to: String },
+    Unreachable { from: String, to: String },
+    Loops { from: String, to: String, steps: usize },
+    NoStart,
+    NeverInSync,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalkError::MissingNode(node) => write!(f, "node `{node}` is not in the network"),
+            WalkError::DanglingEdge { from, to } => {
+                write!(f, "node `{from}` leads to `{to}`, which is not in the network")
+            }
+            WalkError::Unreachable { from, to } => {
+                write!(f, "no path in the network leads from `{from}` to `{to}`")
+            }
+            WalkError::Loops { from, to, steps } => write!(
+                f,
+                "following the instructions from `{from}` loops without reaching `{to}` (gave up after {steps} steps)"
+            ),
+            WalkError::NoStart => write!(f, "no node ends with `A`"),
+            WalkError::NeverInSync => {
+                write!(f, "the ghosts never stand on nodes ending with `Z` at the same time")
+            }
+        }
+    }
+}
 
-        lcm(&results).to_string()
+/// Checks that every left and right neighbour is a node of the network.
+fn check_edges(tree: &BTreeMap<&str, (&str, &str)>) -> Result<(), WalkError> {
+    for (from, (left, right)) in tree {
+        for to in [left, right] {
+            if !tree.contains_key(to) {
+                return Err(WalkError::DanglingEdge { from: from.to_string(), to: to.to_string() });
+            }
+        }
     }
+    Ok(())
 }
 
-fn lcm(nums: &[usize]) -> usize {
-    if nums.len() == 1 {
-        return nums[0]
+/// The network as a graph with `L` and `R` edges.
+fn network_graph<'a>(tree: &BTreeMap<&'a str, (&'a str, &'a str)>) -> Graph<&'a str, char> {
+    let mut graph = Graph::new();
+    for (node, (left, right)) in tree {
+        graph.add_edge(*node, *left, 'L');
+        graph.add_edge(*node, *right, 'R');
     }
-    let a = nums[0];
-    let b = lcm(&nums[1..]);
-    a * b / gcd(a, b)
+    graph
 }
 
-fn gcd(a: usize, b: usize) -> usize {
-    if b == 0 {
-        return a
+/// Number of steps following the instructions from `from` until `to` is
+/// reached, at least one.
+///
+/// The walk is in the same state whenever it is on the same node at the same
+/// instruction, so if `to` isn't reached within `nodes × instructions` steps
+/// it never will be.
+fn steps_between(
+    tree: &BTreeMap<&str, (&str, &str)>,
+    instructions: &[Direction],
+    from: &str,
+    to: &str,
+) -> Result<usize, WalkError> {
+    for node in [from, to] {
+        if !tree.contains_key(node) {
+            return Err(WalkError::MissingNode(node.to_string()));
+        }
     }
-    gcd(b, a % b)
+    check_edges(tree)?;
+    // The first step leaves `from`, so walking to itself needs a way back
+    let graph = network_graph(tree);
+    let [from_index, to_index] = [from, to].map(|node| {
+        graph.index_of(&node).expect("checked to be in the network")
+    });
+    if !graph
+        .edges(from_index)
+        .iter()
+        .any(|(next, _)| graph.bfs(*next)[to_index].is_some())
+    {
+        return Err(WalkError::Unreachable { from: from.to_string(), to: to.to_string() });
+    }
+
+    let limit = tree.len() * instructions.len();
+    let mut node = from;
+    for (step, direction) in instructions.iter().cycle().take(limit).enumerate() {
+        let (left, right) = tree[node];
+        node = match direction {
+            Direction::LEFT => left,
+            Direction::RIGHT => right,
+        };
+        if node == to {
+            return Ok(step + 1);
+        }
+    }
+    Err(WalkError::Loops { from: from.to_string(), to: to.to_string(), steps: limit })
+}
+
+/// The steps at which a ghost stands on a node ending with `Z`.
+///
+/// A ghost's state is its node together with its position in the
+/// instructions, so after at most `nodes × instructions` steps it repeats
+/// forever.
+#[derive(Debug, PartialEq, Eq)]
+struct GhostCycle {
+    /// Steps taken before the repeating part of the walk starts.
+    offset: usize,
+    length: usize,
+    /// Every hit, in steps from the start, up to `offset + length`. Hits from
+    /// `offset` onwards repeat every `length` steps.
+    hits: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn hits_at(&self, step: usize) -> bool {
+        let step = if step < self.offset {
+            step
+        } else {
+            self.offset + (step - self.offset) % self.length
+        };
+        self.hits.binary_search(&step).is_ok()
+    }
+
+    fn cycle_hits(&self) -> impl Iterator<Item = usize> + '_ {
+        self.hits.iter().copied().filter(|hit| *hit >= self.offset)
+    }
+}
+
+fn ghost_cycle(
+    tree: &BTreeMap<&str, (&str, &str)>,
+    instructions: &[Direction],
+    start: &str,
+) -> Result<GhostCycle, WalkError> {
+    let mut seen: HashMap<(&str, usize), usize> = HashMap::new();
+    let mut hits = vec![];
+    let mut node = start;
+
+    for step in 0.. {
+        let instruction = step % instructions.len();
+        if let Some(offset) = seen.insert((node, instruction), step) {
+            return Ok(GhostCycle { offset, length: step - offset, hits });
+        }
+        if node.ends_with('Z') {
+            hits.push(step);
+        }
+
+        let (left, right) = tree
+            .get(node)
+            .ok_or_else(|| WalkError::MissingNode(node.to_string()))?;
+        node = match instructions[instruction] {
+            Direction::LEFT => left,
+            Direction::RIGHT => right,
+        };
+    }
+    unreachable!("the walk repeats within nodes × instructions steps")
+}
+
+/// The first step at which every ghost stands on a `Z` node at once.
+///
+/// Steps before the last ghost enters its cycle are checked one by one.
+/// After that each ghost only needs the step to land on one of its cycle
+/// hits, so every combination of hits is merged with the Chinese remainder
+/// theorem and the smallest solution wins.
+fn earliest_common_hit(cycles: &[GhostCycle]) -> Result<u64, WalkError> {
+    let settled = cycles
+        .iter()
+        .map(|cycle| cycle.offset)
+        .max()
+        .ok_or(WalkError::NoStart)?;
+
+    if let Some(step) = (0..settled).find(|step| cycles.iter().all(|cycle| cycle.hits_at(*step))) {
+        return Ok(step as u64);
+    }
+
+    let solutions = cycles.iter().fold(vec![(0u128, 1u128)], |solutions, cycle| {
+        let length = cycle.length as u128;
+        solutions
+            .iter()
+            .flat_map(|(residue, modulus)| {
+                cycle.cycle_hits().filter_map(move |hit| {
+                    math::crt(*residue, *modulus, hit as u128 % length, length)
+                })
+            })
+            .sorted()
+            .dedup()
+            .collect()
+    });
+
+    solutions
+        .into_iter()
+        .map(|(residue, modulus)| {
+            // Smallest step past `settled` congruent to `residue`
+            let settled = settled as u128;
+            if residue >= settled {
+                residue
+            } else {
+                residue + (settled - residue).div_ceil(modulus) * modulus
+            }
+        })
+        .min()
+        .map(|step| step as u64)
+        .ok_or(WalkError::NeverInSync)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::days::day08::day_eight::DayEight;
+    use std::collections::BTreeMap;
+
+    use nom::combinator::all_consuming;
+
+    use crate::days::day08::day_eight::{DayEight, Direction, GhostCycle, WalkError, earliest_common_hit, ghost_cycle, parse_instruction, parse_tree, steps_between, to_dot};
     use crate::problem::Problem;
+    use crate::testing::{Rng, mutations, replace_one};
+
+    fn random_node(rng: &mut Rng) -> String {
+        (0..3).map(|_| *rng.pick(&['A', 'B', 'Z', 'X', '1', '2'])).collect()
+    }
+
+    fn random_network(rng: &mut Rng) -> Vec<String> {
+        (0..rng.between(1, 8))
+            .map(|_| format!("{} = ({}, {})", random_node(rng), random_node(rng), random_node(rng)))
+            .collect()
+    }
 
     #[test]
     fn _part_one() {
@@ -171,21 +385,194 @@ CCC = (ZZZ, GGG)
 DDD = (DDD, DDD)
 EEE = (EEE, EEE)
 GGG = (GGG, GGG)
+ZZZ = (ZZZ, ZZZ)";
+        assert_eq!(DayEight.part_one(input), "2");
+
+        let input = "LLR
+
+AAA = (BBB, BBB)
+BBB = (AAA, ZZZ)
 ZZZ = (ZZZ, ZZZ)";
         assert_eq!(DayEight.part_one(input), "6")
     }
 
+    fn network(input: &str) -> (Vec<Direction>, BTreeMap<&str, (&str, &str)>) {
+        let (input, instructions) = parse_instruction(input).unwrap();
+        let tree = input
+            .lines()
+            .filter_map(|line| parse_tree(line).ok().map(|(_, node)| node))
+            .collect();
+        (instructions, tree)
+    }
+
+    #[test]
+    fn dot_export() {
+        let input = "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+XXX = (XXX, XXX)";
+        assert_eq!(
+            to_dot(input, None),
+            r#"digraph {
+    "11A" [style=filled, fillcolor=palegreen];
+    "11B" [color=blue, penwidth=2];
+    "XXX";
+    "11Z" [style=filled, fillcolor=salmon, color=blue, penwidth=2];
+    "11A" -> "11B" [label="L"];
+    "11A" -> "XXX" [label="R"];
+    "11B" -> "XXX" [label="L"];
+    "11B" -> "11Z" [label="R", color=blue, penwidth=2];
+    "XXX" -> "XXX" [label="L"];
+    "XXX" -> "XXX" [label="R"];
+    "11Z" -> "11B" [label="L", color=blue, penwidth=2];
+    "11Z" -> "XXX" [label="R"];
+}"#
+        );
+        assert!(to_dot(input, Some(2)).contains("label=\"2 of 4 nodes shown\""));
+    }
+
+    #[test]
+    fn unreachable_destination() {
+        let (instructions, tree) = network("L
+
+AAA = (BBB, BBB)
+BBB = (AAA, CCC)");
+        assert_eq!(
+            steps_between(&tree, &instructions, "AAA", "ZZZ"),
+            Err(WalkError::MissingNode("ZZZ".to_string()))
+        );
+        assert_eq!(
+            steps_between(&tree, &instructions, "QQQ", "AAA"),
+            Err(WalkError::MissingNode("QQQ".to_string()))
+        );
+        assert_eq!(
+            steps_between(&tree, &instructions, "AAA", "BBB"),
+            Err(WalkError::DanglingEdge { from: "BBB".to_string(), to: "CCC".to_string() })
+        );
+
+        let (instructions, tree) = network("L
+
+AAA = (BBB, BBB)
+BBB = (AAA, AAA)
+ZZZ = (AAA, AAA)");
+        assert_eq!(
+            steps_between(&tree, &instructions, "AAA", "ZZZ"),
+            Err(WalkError::Unreachable { from: "AAA".to_string(), to: "ZZZ".to_string() })
+        );
+
+        // ZZZ is only ever to the right of BBB, which is always left at an L
+        let (instructions, tree) = network("LR
+
+AAA = (AAA, BBB)
+BBB = (AAA, ZZZ)
+ZZZ = (ZZZ, ZZZ)");
+        assert_eq!(
+            steps_between(&tree, &instructions, "AAA", "ZZZ"),
+            Err(WalkError::Loops { from: "AAA".to_string(), to: "ZZZ".to_string(), steps: 6 })
+        );
+        assert_eq!(steps_between(&tree, &instructions, "AAA", "AAA"), Ok(1));
+    }
+
     #[test]
     fn _part_two() {
-        let input = "RL
+        let input = "LR
 
-AAA = (BBB, CCC)
-BBB = (DDD, EEE)
-CCC = (ZZZ, GGG)
-DDD = (DDD, DDD)
-EEE = (EEE, EEE)
-GGG = (GGG, GGG)
-ZZZ = (ZZZ, ZZZ)";
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)";
         assert_eq!(DayEight.part_two(input), "6")
     }
+
+    #[test]
+    fn ghost_cycles() {
+        let input = "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)";
+        let (input, instructions) = parse_instruction(input).unwrap();
+        let tree = input
+            .lines()
+            .filter_map(|line| parse_tree(line).ok().map(|(_, node)| node))
+            .collect::<BTreeMap<&str, (&str, &str)>>();
+
+        assert_eq!(
+            ghost_cycle(&tree, &instructions, "11A"),
+            Ok(GhostCycle { offset: 1, length: 2, hits: vec![2] })
+        );
+        assert_eq!(
+            ghost_cycle(&tree, &instructions, "22A"),
+            Ok(GhostCycle { offset: 1, length: 6, hits: vec![3, 6] })
+        );
+        assert_eq!(
+            ghost_cycle(&tree, &instructions, "XXX"),
+            Ok(GhostCycle { offset: 0, length: 2, hits: vec![] })
+        );
+    }
+
+    #[test]
+    fn offset_cycles() {
+        // Hits in the prefix, several hits per cycle and cycle lengths that
+        // aren't their own offsets
+        let cycles = [
+            GhostCycle { offset: 3, length: 4, hits: vec![1, 5] },
+            GhostCycle { offset: 0, length: 6, hits: vec![1, 3] },
+            GhostCycle { offset: 2, length: 5, hits: vec![4, 6] },
+        ];
+        let brute = (0..1_000)
+            .find(|step| cycles.iter().all(|cycle| cycle.hits_at(*step)))
+            .map(|step| step as u64);
+        assert_eq!(earliest_common_hit(&cycles).ok(), brute);
+        assert_eq!(earliest_common_hit(&cycles[..2]), Ok(1));
+
+        let cycles = [
+            GhostCycle { offset: 0, length: 2, hits: vec![0] },
+            GhostCycle { offset: 0, length: 4, hits: vec![1] },
+        ];
+        assert_eq!(earliest_common_hit(&cycles), Err(WalkError::NeverInSync));
+        assert_eq!(earliest_common_hit(&[]), Err(WalkError::NoStart));
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(8);
+        for _ in 0..200 {
+            for line in random_network(&mut rng) {
+                let (_, (source, (left, right))) = all_consuming(parse_tree)(&line).unwrap();
+                let printed = format!("{source} = ({left}, {right})");
+                assert_eq!(printed, line);
+                assert_eq!(all_consuming(parse_tree)(&printed).unwrap().1, (source, (left, right)));
+            }
+        }
+    }
+
+    #[test]
+    fn mutated_networks_fail_cleanly() {
+        let mut rng = Rng::new(8);
+        for _ in 0..200 {
+            for line in random_network(&mut rng) {
+                for mutated in mutations(&mut rng, &line) {
+                    let parsed = all_consuming(parse_tree)(&mutated);
+                    if mutated.matches(['=', '(', ',', ')']).count() < 4 {
+                        assert!(parsed.is_err(), "{mutated}");
+                    }
+                }
+
+                let broken = replace_one(&mut rng, &line, |_, c| "=(,)".contains(c), "#").unwrap();
+                assert!(all_consuming(parse_tree)(&broken).is_err(), "{broken}");
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,67 +1,219 @@
+use std::fmt;
+
 use itertools::Itertools;
 use nom::character::complete;
 use nom::character::complete::{line_ending, space1};
-use nom::{IResult, Parser};
+use nom::IResult;
 use nom::multi::separated_list1;
 use crate::problem::Problem;
 
 pub(crate) struct DayNine;
 
-fn part_one(top_row: Vec<i32>) -> i64 {
-    let mut rows: Vec<Vec<i32>> = vec![top_row];
-    while rows.last().unwrap().len() > 1 &&
-        !rows.last().unwrap().iter().all(|x| *x == 0)
-    {
-        let row = rows.last().unwrap();
-        rows.push(
-            row
-                .iter()
-                .tuple_windows()
-                .fold(Vec::new(), |mut acc, (x, y)| {
-                    acc.push(*y - *x);
-                    acc
-                })
-        )
+#[derive(Debug, PartialEq, Eq)]
+enum SequenceError {
+    Empty,
+    /// The differences ran out before reaching a row of zeroes, so the
+    /// values don't pin down a polynomial.
+    NotPolynomial,
+    Overflow,
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceError::Empty => write!(f, "the sequence has no values"),
+            SequenceError::NotPolynomial => {
+                write!(f, "the differences never become all zero, so there is nothing to extrapolate")
+            }
+            SequenceError::Overflow => write!(f, "a difference or prediction overflowed"),
+        }
     }
-    rows
-        .iter()
-        .rev()
-        .fold(0, |mut acc, row| {
-            acc += *row.last().unwrap() as i64;
-            acc
-        })
 }
 
-fn part_two(top_row: Vec<i32>) -> i64 {
-    let mut rows: Vec<Vec<i32>> = vec![top_row];
-    while rows.last().unwrap().len() > 1 &&
-        !rows.last().unwrap().iter().all(|x| *x == 0)
-    {
-        let row = rows.last().unwrap();
-        rows.push(
-            row
+/// Signed integers a difference table can be built from.
+trait Signed: Copy + PartialEq + fmt::Display {
+    const ZERO: Self;
+
+    fn checked_sub(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! impl_signed {
+    ($($t:ty),*) => {
+        $(
+            impl Signed for $t {
+                const ZERO: Self = 0;
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$t>::checked_sub(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_signed!(i8, i16, i32, i64, i128, isize);
+
+/// The rows of repeated differences below a sequence, down to the first row
+/// of zeroes or the last single value, whichever comes first.
+#[derive(Debug, PartialEq, Eq)]
+struct DifferenceTable<T> {
+    rows: Vec<Vec<T>>,
+}
+
+impl<T: Signed> DifferenceTable<T> {
+    fn new(values: &[T]) -> Result<Self, SequenceError> {
+        let mut rows = vec![values.to_vec()];
+        loop {
+            let row = rows.last().expect("the values are the first row");
+            if row.len() <= 1 || row.iter().all(|value| *value == T::ZERO) {
+                return Ok(DifferenceTable { rows });
+            }
+            let next = row
                 .iter()
                 .tuple_windows()
-                .fold(Vec::new(), |mut acc, (x, y)| {
-                    acc.push(*x - *y);
-                    acc
-                })
-        )
+                .map(|(x, y)| y.checked_sub(*x).ok_or(SequenceError::Overflow))
+                .collect::<Result<Vec<T>, SequenceError>>()?;
+            rows.push(next);
+        }
+    }
+
+    /// Whether the differences die out, i.e. the values come from a
+    /// polynomial of lower degree than their count.
+    fn reaches_zero(&self) -> bool {
+        self.rows
+            .last()
+            .is_some_and(|row| row.iter().all(|value| *value == T::ZERO))
+    }
+}
+
+/// Draws the table the way the puzzle does, each row shifted to sit between
+/// the values above it.
+impl<T: Signed> fmt::Display for DifferenceTable<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widest = self
+            .rows
+            .iter()
+            .flatten()
+            .map(|value| value.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let cell = (widest + 2).next_multiple_of(2);
+
+        let lines = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(depth, row)| {
+                let values = row.iter().map(|value| format!("{value:>cell$}")).join("");
+                format!("{}{values}", " ".repeat(depth * cell / 2))
+            })
+            .collect::<Vec<String>>();
+        let indent = lines
+            .iter()
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        write!(f, "{}", lines.iter().map(|line| &line[indent..]).join("\n"))
+    }
+}
+
+/// A sequence of values produced by some polynomial, stored as the first
+/// value of every row of its difference triangle.
+#[derive(Debug, PartialEq, Eq)]
+struct Sequence {
+    leading_differences: Vec<i128>,
+}
+
+impl Sequence {
+    fn new(values: &[i64]) -> Result<Self, SequenceError> {
+        if values.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+
+        let values = values.iter().map(|value| *value as i128).collect::<Vec<i128>>();
+        let table = DifferenceTable::new(&values)?;
+        if !table.reaches_zero() {
+            return Err(SequenceError::NotPolynomial);
+        }
+
+        let leading_differences = table
+            .rows
+            .iter()
+            .take_while(|row| row.iter().any(|value| *value != 0))
+            .map(|row| row[0])
+            .collect();
+        Ok(Sequence { leading_differences })
+    }
+
+    /// Degree of the polynomial behind the values. A sequence of zeroes
+    /// counts as degree zero.
+    fn degree(&self) -> usize {
+        self.leading_differences.len().saturating_sub(1)
+    }
+
+    /// The value at position `k`, where the given values sit at `0..len`.
+    /// Negative positions extrapolate backwards.
+    ///
+    /// Uses Newton's forward difference formula,
+    /// `a(k) = Σ C(k, j) · Δʲa(0)`, where the binomial coefficient is
+    /// generalised to negative `k`.
+    fn predict(&self, k: i64) -> Result<i128, SequenceError> {
+        let k = k as i128;
+        let mut binomial = 1i128;
+        let mut value = 0i128;
+        for (j, difference) in self.leading_differences.iter().enumerate() {
+            if j > 0 {
+                // C(k, j) = C(k, j - 1) · (k - j + 1) / j, which divides exactly
+                binomial = binomial
+                    .checked_mul(k - j as i128 + 1)
+                    .ok_or(SequenceError::Overflow)?
+                    / j as i128;
+            }
+            value = binomial
+                .checked_mul(*difference)
+                .and_then(|term| value.checked_add(term))
+                .ok_or(SequenceError::Overflow)?;
+        }
+        Ok(value)
+    }
+}
+
+/// Shows the difference table of every line, or only of line `line`
+/// (counting from one), with the degree and both extrapolated values. `None`
+/// when there is no line `line`.
+pub(crate) fn explain(input: &str, line: Option<usize>) -> Option<String> {
+    let (_, lines) = parse(input).expect("Must parse");
+    if line.is_some_and(|line| !(1..=lines.len()).contains(&line)) {
+        return None;
     }
-    rows
+    let explanation = lines
         .iter()
-        .rev()
-        .fold(0, |mut acc, row| {
-            acc += *row.first().unwrap() as i64;
-            acc
+        .enumerate()
+        .filter(|(index, _)| line.is_none_or(|line| line == index + 1))
+        .map(|(index, values)| {
+            let table = DifferenceTable::new(values)
+                .map_or_else(|e| e.to_string(), |table| table.to_string());
+            let summary = match Sequence::new(values) {
+                Ok(sequence) => format!(
+                    "degree {}, previous {}, next {}",
+                    sequence.degree(),
+                    sequence.predict(-1).map_or_else(|e| e.to_string(), |value| value.to_string()),
+                    sequence
+                        .predict(values.len() as i64)
+                        .map_or_else(|e| e.to_string(), |value| value.to_string()),
+                ),
+                Err(e) => e.to_string(),
+            };
+            format!("line {}: {summary}\n{table}", index + 1)
         })
+        .join("\n\n");
+    Some(explanation)
 }
 
-
-fn parse(input: &str) -> IResult<&str, Vec<Vec<i32>>> {
+fn parse(input: &str) -> IResult<&str, Vec<Vec<i64>>> {
     separated_list1(
         line_ending,
-        separated_list1(space1, complete::i32))
+        separated_list1(space1, complete::i64))
         (input)
 }
 
@@ -71,8 +223,10 @@ impl Problem for DayNine {
         lines
             .into_iter()
             .map(|row| {
-                part_one(row)
-            }).sum::<i64>()
+                Sequence::new(&row)
+                    .and_then(|sequence| sequence.predict(row.len() as i64))
+                    .unwrap_or_else(|e| panic!("{e}"))
+            }).sum::<i128>()
             .to_string()
     }
 
@@ -81,8 +235,10 @@ impl Problem for DayNine {
         lines
             .into_iter()
             .map(|row| {
-                part_two(row)
-            }).sum::<i64>()
+                Sequence::new(&row)
+                    .and_then(|sequence| sequence.predict(-1))
+                    .unwrap_or_else(|e| panic!("{e}"))
+            }).sum::<i128>()
             .to_string()
     }
 }
@@ -90,9 +246,33 @@ impl Problem for DayNine {
 
 #[cfg(test)]
 mod tests {
-    use crate::days::day10::day_ten::DayTen;
+    use nom::combinator::all_consuming;
+
+    use crate::testing::{Rng, mutations, replace_one};
+
     use super::*;
 
+    fn random_history(rng: &mut Rng) -> Vec<Vec<i64>> {
+        (0..rng.between(1, 6))
+            .map(|_| {
+                (0..rng.between(1, 10))
+                    .map(|_| match rng.below(4) {
+                        0 => rng.next() as i64,
+                        _ => rng.below(2_000) as i64 - 1_000,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn print_history(history: &[Vec<i64>]) -> String {
+        history
+            .iter()
+            .map(|values| values.iter().map(i64::to_string).collect::<Vec<String>>().join(" "))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
     #[test]
     fn _part_one() {
         let input = "0 3 6 9 12 15
@@ -108,4 +288,98 @@ mod tests {
 10 13 16 21 30 45";
         assert_eq!(DayNine.part_two(input), "2")
     }
+
+    #[test]
+    fn arbitrary_offsets() {
+        // n² + 1
+        let sequence = Sequence::new(&[1, 2, 5, 10, 17]).unwrap();
+        assert_eq!(sequence.degree(), 2);
+        for k in -20..20 {
+            assert_eq!(sequence.predict(k), Ok(k as i128 * k as i128 + 1));
+        }
+
+        let sequence = Sequence::new(&[10, 13, 16, 21, 30, 45]).unwrap();
+        assert_eq!(sequence.degree(), 3);
+        assert_eq!(sequence.predict(6), Ok(68));
+        assert_eq!(sequence.predict(-1), Ok(5));
+
+        assert_eq!(Sequence::new(&[7, 7]).unwrap().degree(), 0);
+        assert_eq!(Sequence::new(&[0, 0, 0]).unwrap().predict(100), Ok(0));
+    }
+
+    #[test]
+    fn unpredictable_sequences() {
+        assert_eq!(Sequence::new(&[]), Err(SequenceError::Empty));
+        assert_eq!(Sequence::new(&[1, 2, 4, 8]), Err(SequenceError::NotPolynomial));
+        assert_eq!(Sequence::new(&[5]), Err(SequenceError::NotPolynomial));
+
+        // n³
+        let sequence = Sequence::new(&[0, 1, 8, 27, 64]).unwrap();
+        assert_eq!(sequence.predict(1_000_000), Ok(1_000_000_000_000_000_000));
+        assert_eq!(sequence.predict(i64::MAX), Err(SequenceError::Overflow));
+    }
+
+    #[test]
+    fn difference_tables() {
+        let table = DifferenceTable::new(&[0i8, 3, 6, 9, 12, 15]).unwrap();
+        assert_eq!(
+            table.to_string(),
+            "0   3   6   9  12  15
+  3   3   3   3   3
+    0   0   0   0"
+        );
+
+        let table = DifferenceTable::new(&[10i64, 13, 16, 21, 30, 45]).unwrap();
+        assert_eq!(table.rows.len(), 5);
+        assert_eq!(
+            table.to_string(),
+            "10  13  16  21  30  45
+   3   3   5   9  15
+     0   2   4   6
+       2   2   2
+         0   0"
+        );
+
+        assert!(!DifferenceTable::new(&[1i32, 2, 4, 8]).unwrap().reaches_zero());
+        assert_eq!(DifferenceTable::new(&[i8::MIN, i8::MAX]), Err(SequenceError::Overflow));
+    }
+
+    #[test]
+    fn explained_lines() {
+        let input = "0 3 6 9 12 15
+1 3 6 10 15 21";
+        assert!(explain(input, Some(2)).unwrap().starts_with("line 2: degree 2, previous 0, next 28\n"));
+        assert_eq!(explain(input, None).unwrap().matches("line ").count(), 2);
+        assert_eq!(explain(input, Some(0)), None);
+        assert_eq!(explain(input, Some(3)), None);
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(9);
+        for _ in 0..200 {
+            let history = random_history(&mut rng);
+            let input = print_history(&history);
+            assert_eq!(all_consuming(parse)(&input).unwrap().1, history);
+        }
+    }
+
+    #[test]
+    fn mutated_histories_fail_cleanly() {
+        let mut rng = Rng::new(9);
+        for _ in 0..200 {
+            let input = print_history(&random_history(&mut rng));
+            for mutated in mutations(&mut rng, &input) {
+                let parsed = all_consuming(parse)(&mutated);
+                if mutated.contains(|c: char| !c.is_ascii_digit() && !" -+\n".contains(c)) {
+                    assert!(parsed.is_err(), "{mutated}");
+                }
+            }
+
+            let broken = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "#").unwrap();
+            assert!(all_consuming(parse)(&broken).is_err(), "{broken}");
+            let overflowing = replace_one(&mut rng, &input, |_, c| c.is_ascii_digit(), "99999999999999999999").unwrap();
+            assert!(all_consuming(parse)(&overflowing).is_err(), "{overflowing}");
+        }
+    }
 }
@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+
 use glam::IVec2;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
@@ -8,11 +10,12 @@ use nom::{IResult, Parser};
 use nom::multi::many1;
 use nom::sequence::terminated;
 use nom_locate::LocatedSpan;
+use crate::graph::Graph;
 use crate::problem::Problem;
 
 pub(crate) struct DayTen;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum PipeType {
     Vertical,
     Horizontal,
@@ -24,15 +27,7 @@ enum PipeType {
     Ground,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-enum Direction {
-    North,
-    South,
-    East,
-    West,
-}
-
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum Status {
     In,
     Out,
@@ -44,6 +39,329 @@ struct PipeInfo<'a> {
     pipe_type: PipeType,
 }
 
+impl PipeType {
+    /// Offsets of the neighbours a pipe connects to. The starting position
+    /// could be any pipe, so it is open on every side.
+    fn connections(&self) -> &'static [IVec2] {
+        const NORTH: IVec2 = IVec2::new(0, -1);
+        const SOUTH: IVec2 = IVec2::new(0, 1);
+        const EAST: IVec2 = IVec2::new(1, 0);
+        const WEST: IVec2 = IVec2::new(-1, 0);
+
+        match self {
+            PipeType::Vertical => &[NORTH, SOUTH],
+            PipeType::Horizontal => &[EAST, WEST],
+            PipeType::NorthEast => &[NORTH, EAST],
+            PipeType::NorthWest => &[NORTH, WEST],
+            PipeType::SouthWest => &[SOUTH, WEST],
+            PipeType::SouthEast => &[SOUTH, EAST],
+            PipeType::StartingPosition => &[NORTH, SOUTH, EAST, WEST],
+            PipeType::Ground => &[],
+        }
+    }
+
+    fn glyph(&self) -> char {
+        match self {
+            PipeType::Vertical => '│',
+            PipeType::Horizontal => '─',
+            PipeType::NorthEast => '└',
+            PipeType::NorthWest => '┘',
+            PipeType::SouthWest => '┐',
+            PipeType::SouthEast => '┌',
+            PipeType::StartingPosition => 'S',
+            PipeType::Ground => '·',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MazeError {
+    NoStart,
+    MultipleStarts(Vec<IVec2>),
+    /// The start has to connect to exactly two pipes to be part of a loop.
+    StartConnections(Vec<IVec2>),
+    /// Following the loop led to a tile that doesn't connect back.
+    Broken { from: IVec2, to: IVec2 },
+}
+
+impl fmt::Display for MazeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MazeError::NoStart => write!(f, "the maze has no starting position `S`"),
+            MazeError::MultipleStarts(starts) => {
+                write!(f, "the maze has {} starting positions at {starts:?}", starts.len())
+            }
+            MazeError::StartConnections(neighbours) if neighbours.is_empty() => {
+                write!(f, "no pipe connects to the starting position")
+            }
+            MazeError::StartConnections(neighbours) => write!(
+                f,
+                "the starting position needs exactly two connecting pipes, found {} at {neighbours:?}",
+                neighbours.len()
+            ),
+            MazeError::Broken { from, to } => {
+                write!(f, "the loop is not closed: {from} leads to {to}, which does not connect back")
+            }
+        }
+    }
+}
+
+/// Finds the starting position and replaces it in the grid with the pipe its
+/// two connecting neighbours imply.
+fn infer_start(grid: &mut HashMap<IVec2, PipeType>) -> Result<IVec2, MazeError> {
+    let starts = grid
+        .iter()
+        .filter_map(|(position, pipe_type)| {
+            (pipe_type == &PipeType::StartingPosition).then_some(*position)
+        })
+        .collect::<Vec<IVec2>>();
+    let start = match starts[..] {
+        [] => return Err(MazeError::NoStart),
+        [start] => start,
+        _ => return Err(MazeError::MultipleStarts(starts)),
+    };
+
+    let offsets = PipeType::StartingPosition.connections()
+        .iter()
+        .filter(|offset| {
+            grid.get(&(start + **offset))
+                .is_some_and(|pipe_type| pipe_type.connections().contains(&-**offset))
+        })
+        .collect::<Vec<&IVec2>>();
+    let pipe_type = [
+        PipeType::Vertical,
+        PipeType::Horizontal,
+        PipeType::NorthEast,
+        PipeType::NorthWest,
+        PipeType::SouthWest,
+        PipeType::SouthEast,
+    ]
+        .into_iter()
+        .find(|pipe_type| {
+            offsets.len() == 2 && offsets.iter().all(|offset| pipe_type.connections().contains(offset))
+        })
+        .ok_or_else(|| {
+            MazeError::StartConnections(offsets.iter().map(|offset| start + **offset).collect())
+        })?;
+
+    grid.insert(start, pipe_type);
+    Ok(start)
+}
+
+/// The tiles of the loop through `start`, in walking order and beginning
+/// with `start`. The start must already have its real pipe type.
+fn trace_loop(grid: &HashMap<IVec2, PipeType>, start: IVec2) -> Result<Vec<IVec2>, MazeError> {
+    let mut tiles = vec![start];
+    let mut previous = None;
+    let mut current = start;
+    loop {
+        let pipe_type = grid.get(&current).unwrap_or(&PipeType::Ground);
+        let offset = pipe_type
+            .connections()
+            .iter()
+            .find(|offset| Some(current + **offset) != previous)
+            .ok_or(MazeError::Broken { from: previous.unwrap_or(current), to: current })?;
+        let next = current + *offset;
+
+        let connects_back = grid
+            .get(&next)
+            .is_some_and(|other| other.connections().contains(&-*offset));
+        if !connects_back {
+            return Err(MazeError::Broken { from: current, to: next });
+        }
+        if next == start {
+            return Ok(tiles);
+        }
+        // Two openings per pipe can't revisit a tile without passing the start
+        assert!(tiles.len() <= grid.len(), "the loop walk should end at the start");
+
+        tiles.push(next);
+        previous = Some(current);
+        current = next;
+    }
+}
+
+/// Links every pair of neighbouring tiles whose pipes open towards each
+/// other.
+fn pipe_graph(grid: &HashMap<IVec2, PipeType>) -> Graph<IVec2, ()> {
+    let mut graph = Graph::new();
+    for (position, pipe_type) in grid {
+        graph.add_node(*position);
+        for offset in pipe_type.connections() {
+            let neighbour = *position + *offset;
+            let connects_back = grid
+                .get(&neighbour)
+                .is_some_and(|other| other.connections().contains(&-*offset));
+            if connects_back {
+                graph.add_edge(*position, neighbour, ());
+            }
+        }
+    }
+    graph
+}
+
+/// How [`PipeMaze::enclosed_tiles`] counts the tiles inside the loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AreaMethod {
+    /// Scans each row, flipping between outside and inside at every pipe that
+    /// reaches south of the row's centre line.
+    Scan,
+    /// Takes the loop's area with the shoelace formula and solves Pick's
+    /// theorem, `area = inside + boundary / 2 - 1`, for the inside tiles.
+    Shoelace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RenderStyle {
+    /// Loop pipes as box drawing, every other tile as `I` or `O`.
+    Plain,
+    /// Loop pipes in bold yellow with the start in red, other tiles dimmed
+    /// and the ones inside the loop on a green background.
+    Ansi,
+}
+
+/// A grid of pipes with its starting position resolved to a real pipe and
+/// the loop through it traced.
+#[derive(Debug)]
+struct PipeMaze {
+    grid: HashMap<IVec2, PipeType>,
+    graph: Graph<IVec2, ()>,
+    start: IVec2,
+    loop_tiles: Vec<IVec2>,
+    width: i32,
+    height: i32,
+}
+
+impl PipeMaze {
+    fn new(mut grid: HashMap<IVec2, PipeType>) -> Result<Self, MazeError> {
+        let start = infer_start(&mut grid)?;
+        let loop_tiles = trace_loop(&grid, start)?;
+        let graph = pipe_graph(&grid);
+        let width = grid.keys().map(|position| position.x).max().unwrap_or(-1) + 1;
+        let height = grid.keys().map(|position| position.y).max().unwrap_or(-1) + 1;
+        Ok(PipeMaze { grid, graph, start, loop_tiles, width, height })
+    }
+
+    fn start(&self) -> IVec2 {
+        self.start
+    }
+
+    /// The tiles of the loop in walking order, beginning with the start.
+    fn loop_iter(&self) -> impl Iterator<Item = IVec2> + Clone + '_ {
+        self.loop_tiles.iter().copied()
+    }
+
+    fn loop_length(&self) -> usize {
+        self.loop_tiles.len()
+    }
+
+    /// Steps along the loop to the tile farthest from the start. The start's
+    /// component of the pipe graph is the loop, so this is halfway around it.
+    fn farthest(&self) -> usize {
+        let start = self.graph.index_of(&self.start).expect("the start is in the graph");
+        self.graph
+            .bfs(start)
+            .into_iter()
+            .flatten()
+            .max()
+            .expect("the start is at distance zero")
+    }
+
+    fn enclosed_tiles(&self, method: AreaMethod) -> usize {
+        match method {
+            AreaMethod::Scan => self.inside_tiles().len(),
+            AreaMethod::Shoelace => {
+                let twice_area = self
+                    .loop_iter()
+                    .zip(self.loop_iter().cycle().skip(1))
+                    .map(|(a, b)| a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64)
+                    .sum::<i64>()
+                    .unsigned_abs() as usize;
+                // Every tile of the loop is a lattice point on the boundary
+                (twice_area + 2).saturating_sub(self.loop_length()) / 2
+            }
+        }
+    }
+
+    /// The tiles inside the loop, found with [`AreaMethod::Scan`].
+    fn inside_tiles(&self) -> HashSet<IVec2> {
+        let pipe_locations: HashSet<IVec2> = self.loop_iter().collect();
+
+        (0..self.height)
+            .flat_map(|y| {
+                let mut status = Status::Out;
+                let pipe_locations = &pipe_locations;
+
+                (0..self.width)
+                    .map(move |x| IVec2::new(x, y))
+                    .filter(move |position| {
+                        if pipe_locations.contains(position) {
+                            if self.grid[position].connections().contains(&IVec2::Y) {
+                                status = match status {
+                                    Status::In => Status::Out,
+                                    Status::Out => Status::In,
+                                };
+                            };
+                            false
+                        } else {
+                            status == Status::In
+                        }
+                    })
+            })
+            .collect()
+    }
+
+    /// Draws the maze with box-drawing characters, highlighting the loop and
+    /// marking which tiles it encloses.
+    fn render(&self, style: RenderStyle) -> String {
+        const RESET: &str = "\x1b[0m";
+
+        let inside = self.inside_tiles();
+        let loop_tiles: HashSet<IVec2> = self.loop_iter().collect();
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let position = IVec2::new(x, y);
+                        let Some(pipe_type) = self.grid.get(&position) else {
+                            // Past the end of a row shorter than the widest
+                            return " ".to_string();
+                        };
+                        let glyph = pipe_type.glyph();
+                        match (style, loop_tiles.contains(&position), inside.contains(&position)) {
+                            (RenderStyle::Plain, true, _) => glyph.to_string(),
+                            (RenderStyle::Plain, false, true) => "I".to_string(),
+                            (RenderStyle::Plain, false, false) => "O".to_string(),
+                            (RenderStyle::Ansi, true, _) if position == self.start() => {
+                                format!("\x1b[1;31m{glyph}{RESET}")
+                            }
+                            (RenderStyle::Ansi, true, _) => format!("\x1b[1;33m{glyph}{RESET}"),
+                            (RenderStyle::Ansi, false, true) => format!("\x1b[2;42m{glyph}{RESET}"),
+                            (RenderStyle::Ansi, false, false) => format!("\x1b[2m{glyph}{RESET}"),
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+fn parse_maze(input: &str) -> PipeMaze {
+    let (_, grid) = parse(Span::new(input)).expect("should parse a valid grid");
+    PipeMaze::new(grid).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Parses the maze and draws it, see [`PipeMaze::render`].
+pub(crate) fn render(input: &str, style: RenderStyle) -> String {
+    parse_maze(input).render(style)
+}
+
+/// Counts the tiles enclosed by the loop with the given method.
+pub(crate) fn enclosed(input: &str, method: AreaMethod) -> String {
+    parse_maze(input).enclosed_tiles(method).to_string()
+}
+
 type Span<'a> = LocatedSpan<&'a str>;
 type SpanIVec2<'a> = LocatedSpan<&'a str, IVec2>;
 
@@ -130,257 +448,11 @@ fn parse(
 impl Problem for DayTen {
     fn part_one(&self, input: &str,
     ) -> String {
-        let (_, grid) = parse(Span::new(input)).expect("Should parse");
-        let start = grid
-            .iter()
-            .find_map(|(key, value)| {
-                (value == &PipeType::StartingPosition)
-                    .then_some(key)
-            }).expect("Starting pos must exist");
-
-        let north = *start + IVec2::new(0, -1);
-        let north_position = grid
-            .get(&north)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Vertical
-                | PipeType::SouthWest
-                | PipeType::SouthEast => true,
-                _ => false,
-            })
-            .then_some((Direction::South, north));
-        let south = *start + IVec2::new(0, 1);
-        let south_position = grid
-            .get(&south)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Vertical
-                | PipeType::NorthWest
-                | PipeType::NorthEast => true,
-                _ => false,
-            })
-            .then_some((Direction::North, south));
-        let east = *start + IVec2::new(1, 0);
-        let east_position = grid
-            .get(&east)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Horizontal
-                | PipeType::NorthWest
-                | PipeType::SouthWest => true,
-                _ => false,
-            })
-            .then_some((Direction::West, east));
-        let west = *start + IVec2::new(-1, 0);
-        let west_position = grid
-            .get(&west)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Horizontal
-                | PipeType::NorthEast
-                | PipeType::SouthEast => true,
-                _ => false,
-            })
-            .then_some((Direction::East, west));
-
-        let mut iters = vec![
-            north_position,
-            south_position,
-            east_position,
-            west_position,
-        ]
-            .into_iter()
-            .flatten()
-            .map(|tuple| {
-                std::iter::successors(
-                    Some(tuple),
-                    |(from_direction, current_position)| {
-                        let pipe_type = grid
-                            .get(current_position)
-                            .expect("should not be asking for a grid position that doesn't exist");
-
-                        let direction_to_go = match (from_direction, pipe_type) {
-                            (Direction::North, PipeType::Vertical) => Direction::South,
-                            (Direction::North, PipeType::NorthEast) => Direction::East,
-                            (Direction::North, PipeType::NorthWest) => Direction::West,
-                            (Direction::South, PipeType::Vertical) => Direction::North,
-                            (Direction::South, PipeType::SouthEast) => Direction::East,
-                            (Direction::South, PipeType::SouthWest) => Direction::West,
-                            (Direction::East, PipeType::Horizontal) => Direction::West,
-                            (Direction::East, PipeType::NorthEast) => Direction::North,
-                            (Direction::East, PipeType::SouthEast) => Direction::South,
-                            (Direction::West, PipeType::Horizontal) => Direction::East,
-                            (Direction::West, PipeType::NorthWest) => Direction::North,
-                            (Direction::West, PipeType::SouthWest) => Direction::South,
-                            value => { unreachable!("should not land on Ground or loop around again, {:?}", value); }
-                        };
-                        Some(match direction_to_go {
-                            Direction::North => (Direction::South, *current_position + IVec2::new(0, -1)),
-                            Direction::South => (Direction::North, *current_position + IVec2::new(0, 1)),
-                            Direction::East => (Direction::West, *current_position + IVec2::new(1, 0)),
-                            Direction::West => (Direction::East, *current_position + IVec2::new(-1, 0)),
-                        })
-                    },
-                )
-            });
-
-        let path_a = iters.next().expect("path a should_exist");
-        let path_b = iters.next().expect("path b should exist");
-        let final_position = path_a
-            .zip(path_b)
-            .position(|(a, b)| a.1 == b.1)
-            .expect("should meet in the middle");
-
-        (final_position + 1).to_string()
+        parse_maze(input).farthest().to_string()
     }
 
     fn part_two(&self, input: &str) -> String {
-        let (_input, grid) = parse(Span::new(input))
-            .expect("should parse a valid grid");
-
-        let start_position = grid
-            .iter()
-            .find_map(|(key, value)| {
-                (value == &PipeType::StartingPosition)
-                    .then_some(key)
-            })
-            .expect("should have a starting position");
-
-        let north = *start_position + IVec2::new(0, -1);
-        let north_position = grid
-            .get(&north)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Vertical
-                | PipeType::SouthWest
-                | PipeType::SouthEast => true,
-                _ => false,
-            })
-            .then_some((Direction::South, north));
-        let south = *start_position + IVec2::new(0, 1);
-        let south_position = grid
-            .get(&south)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Vertical
-                | PipeType::NorthWest
-                | PipeType::NorthEast => true,
-                _ => false,
-            })
-            .then_some((Direction::North, south));
-        let east = *start_position + IVec2::new(1, 0);
-        let east_position = grid
-            .get(&east)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Horizontal
-                | PipeType::NorthWest
-                | PipeType::SouthWest => true,
-                _ => false,
-            })
-            .then_some((Direction::West, east));
-        let west = *start_position + IVec2::new(-1, 0);
-        let west_position = grid
-            .get(&west)
-            .is_some_and(|pipe_type| match pipe_type {
-                PipeType::Horizontal
-                | PipeType::NorthEast
-                | PipeType::SouthEast => true,
-                _ => false,
-            })
-            .then_some((Direction::East, west));
-
-        let mut iters = vec![
-            north_position,
-            south_position,
-            east_position,
-            west_position,
-        ]
-            .into_iter()
-            .flatten()
-            .map(|tuple| {
-                std::iter::successors(
-                    Some(tuple),
-                    |(from_direction, current_position)| {
-                        let pipe_type = grid
-                            .get(current_position)
-                            .expect("should not be asking for a grid position that doesn't exist");
-
-                        let direction_to_go = match (from_direction, pipe_type) {
-                            (Direction::North, PipeType::Vertical) => Direction::South,
-                            (Direction::North, PipeType::NorthEast) => Direction::East,
-                            (Direction::North, PipeType::NorthWest) => Direction::West,
-                            (Direction::South, PipeType::Vertical) => Direction::North,
-                            (Direction::South, PipeType::SouthEast) => Direction::East,
-                            (Direction::South, PipeType::SouthWest) => Direction::West,
-                            (Direction::East, PipeType::Horizontal) => Direction::West,
-                            (Direction::East, PipeType::NorthEast) => Direction::North,
-                            (Direction::East, PipeType::SouthEast) => Direction::South,
-                            (Direction::West, PipeType::Horizontal) => Direction::East,
-                            (Direction::West, PipeType::NorthWest) => Direction::North,
-                            (Direction::West, PipeType::SouthWest) => Direction::South,
-                            value => {unreachable!("should not land on Ground or loop around again, {:?}", value);}
-                        };
-                        Some(match direction_to_go {
-                            Direction::North => (Direction::South, *current_position + IVec2::new(0, -1)),
-                            Direction::South => (Direction::North, *current_position + IVec2::new(0, 1)),
-                            Direction::East => (Direction::West, *current_position + IVec2::new(1, 0)),
-                            Direction::West => (Direction::East, *current_position + IVec2::new(-1, 0)),
-                        })
-                    },
-                )
-            });
-
-        let path_a = iters.next().expect("path a should_exist");
-        let path_b = iters.next().expect("path b should exist");
-        let mut zip_it = path_a.zip(path_b);
-        let mut pipe_locations: HashSet<IVec2> =
-            HashSet::from([*start_position]);
-        while let Some((path_a_node, path_b_node)) =
-            zip_it.next()
-        {
-            pipe_locations.insert(path_a_node.1);
-            pipe_locations.insert(path_b_node.1);
-
-            if path_a_node.1 == path_b_node.1 {
-                break;
-            }
-        }
-
-        let result = input
-            .lines()
-            .enumerate()
-            .map(|(y, line)| {
-                let mut status = Status::Out;
-
-                line.chars()
-                    .enumerate()
-                    .filter(|(x, _)| {
-                        let position =
-                            IVec2::new(*x as i32, y as i32);
-                        let pipe_type = grid
-                            .get(&position)
-                            .expect("should be a valid tile");
-                        if pipe_locations.contains(&position) {
-                            if [
-                                PipeType::StartingPosition,
-                                PipeType::Vertical,
-                                PipeType::SouthWest,
-                                PipeType::SouthEast,
-                            ]
-                                .contains(pipe_type)
-                            {
-                                status = match status {
-                                    Status::In => Status::Out,
-                                    Status::Out => Status::In,
-                                };
-                            };
-                            false
-                        } else {
-                            match status {
-                                Status::In => true,
-                                Status::Out => false,
-                            }
-                        }
-                    })
-                    .count()
-            })
-            .sum::<usize>();
-
-        result.to_string()
+        enclosed(input, AreaMethod::Scan)
     }
 }
 
@@ -421,8 +493,32 @@ impl Problem for DayTen {
 
 #[cfg(test)]
 mod tests {
-    use crate::days::day10::day_ten::DayTen;
+    use glam::IVec2;
+
+    use nom::combinator::all_consuming;
+
+    use crate::days::day10::day_ten::{AreaMethod, DayTen, MazeError, PipeMaze, PipeType, RenderStyle, Span, enclosed, infer_start, parse, render, trace_loop};
     use crate::problem::Problem;
+    use crate::testing::{Rng, mutations, replace_one};
+
+    const TILES: [(char, PipeType); 8] = [
+        ('|', PipeType::Vertical),
+        ('-', PipeType::Horizontal),
+        ('L', PipeType::NorthEast),
+        ('J', PipeType::NorthWest),
+        ('7', PipeType::SouthWest),
+        ('F', PipeType::SouthEast),
+        ('S', PipeType::StartingPosition),
+        ('.', PipeType::Ground),
+    ];
+
+    fn random_grid(rng: &mut Rng) -> String {
+        let width = rng.between(1, 12);
+        (0..rng.between(1, 12))
+            .map(|_| (0..width).map(|_| rng.pick(&TILES).0).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
 
     #[test]
     fn part_one() {
@@ -448,4 +544,221 @@ L--J.L7...LJS7F-7L7.
 ....L---J.LJ.LJLJ...";
         assert_eq!(DayTen.part_two(input), "8");
     }
+
+    #[test]
+    fn start_inference() {
+        let input = "-L|F7
+7S-7|
+L|7||
+-L-J|
+L|-JF";
+        let (_, mut grid) = parse(Span::new(input)).unwrap();
+        let start = infer_start(&mut grid).unwrap();
+        assert_eq!(start, IVec2::new(1, 1));
+        assert_eq!(grid[&start], PipeType::SouthEast);
+        assert_eq!(trace_loop(&grid, start).unwrap().len(), 8);
+        assert_eq!(DayTen.part_one(input), "4");
+
+        // S sits on a vertical stretch, which the old scan always assumed
+        let input = "F-7
+|.|
+S.|
+L-J";
+        assert_eq!(DayTen.part_two(input), "2");
+    }
+
+    #[test]
+    fn invalid_starts() {
+        let (_, mut grid) = parse(Span::new("...\n.S.\n...")).unwrap();
+        assert_eq!(infer_start(&mut grid), Err(MazeError::StartConnections(vec![])));
+
+        let (_, mut grid) = parse(Span::new(".|.\n.S.\n...")).unwrap();
+        assert_eq!(
+            infer_start(&mut grid),
+            Err(MazeError::StartConnections(vec![IVec2::new(1, 0)]))
+        );
+
+        let (_, mut grid) = parse(Span::new(".|.\n-S-\n...")).unwrap();
+        assert!(matches!(infer_start(&mut grid), Err(MazeError::StartConnections(neighbours)) if neighbours.len() == 3));
+
+        let (_, mut grid) = parse(Span::new("...\n...")).unwrap();
+        assert_eq!(infer_start(&mut grid), Err(MazeError::NoStart));
+
+        let (_, mut grid) = parse(Span::new("S7.\n|S.")).unwrap();
+        assert!(matches!(infer_start(&mut grid), Err(MazeError::MultipleStarts(_))));
+    }
+
+    #[test]
+    fn open_loops() {
+        // S connects to two pipes, but they never meet again
+        let input = ".....
+.S-7.
+.|.|.
+.L-..
+.....";
+        let (_, mut grid) = parse(Span::new(input)).unwrap();
+        let start = infer_start(&mut grid).unwrap();
+        assert_eq!(
+            trace_loop(&grid, start),
+            Err(MazeError::Broken { from: IVec2::new(2, 3), to: IVec2::new(3, 3) })
+        );
+    }
+
+    #[test]
+    fn area_methods_agree() {
+        let mazes = [
+            ("...........
+.S-------7.
+.|F-----7|.
+.||.....||.
+.||.....||.
+.|L-7.F-J|.
+.|..|.|..|.
+.L--J.L--J.
+...........", "4"),
+            ("..........
+.S------7.
+.|F----7|.
+.||OOOO||.
+.||OOOO||.
+.|L-7F-J|.
+.|II||II|.
+.L--JL--J.
+..........", "4"),
+            (".F----7F7F7F7F-7....
+.|F--7||||||||FJ....
+.||.FJ||||||||L7....
+FJL7L7LJLJ||LJ.L-7..
+L--J.L7...LJS7F-7L7.
+....F-J..F7FJ|L7L7L7
+....L7.F7||L7|.L7L7|
+.....|FJLJ|FJ|F7|.LJ
+....FJL-7.||.||||...
+....L---J.LJ.LJLJ...", "8"),
+            ("FF7FSF7F7F7F7F7F---7
+L|LJ||||||||||||F--J
+FL-7LJLJ||||||LJL-77
+F--JF--7||LJLJ7F7FJ-
+L---JF-JLJ.||-FJLJJ7
+|F|F-JF---7F7-L7L|7|
+|FFJF7L7F-JF7|JL---7
+7-L-JL7||F7|L7F-7F7|
+L.L7LFJ|||||FJL7||LJ
+L7JLJL-JLJLJL--JLJ.L", "10"),
+            ("F-7
+|.|
+S.|
+L-J", "2"),
+        ];
+        for (maze, expected) in mazes {
+            let maze = maze.replace(['O', 'I'], ".");
+            assert_eq!(enclosed(&maze, AreaMethod::Scan), expected);
+            assert_eq!(enclosed(&maze, AreaMethod::Shoelace), expected);
+        }
+    }
+
+    #[test]
+    fn rendering() {
+        let input = "7-F7-
+.FJ|7
+SJLL7
+|F--J
+LJ.LJ";
+        assert_eq!(
+            render(input, RenderStyle::Plain),
+            "OO┌┐O
+O┌┘│O
+┌┘I└┐
+│┌──┘
+└┘OOO"
+        );
+
+        let input = ".....
+.S-7.
+.|F|.
+.L-J.
+.....";
+        assert_eq!(
+            render(input, RenderStyle::Plain),
+            "OOOOO
+O┌─┐O
+O│I│O
+O└─┘O
+OOOOO"
+        );
+        let ansi = render(input, RenderStyle::Ansi);
+        assert!(ansi.starts_with("\x1b[2m·\x1b[0m"));
+        assert!(ansi.contains("\x1b[1;31m┌\x1b[0m"));
+        assert!(ansi.contains("\x1b[2;42m┌\x1b[0m"));
+
+        // Rows of different lengths leave blanks rather than missing tiles
+        assert_eq!(render("F7.\nSJ", RenderStyle::Plain), "┌┐O\n└┘ ");
+    }
+
+    #[test]
+    fn pipe_maze() {
+        let input = "-L|F7
+7S-7|
+L|7||
+-L-J|
+L|-JF";
+        let (_, grid) = parse(Span::new(input)).unwrap();
+        let maze = PipeMaze::new(grid).unwrap();
+        assert_eq!(maze.start(), IVec2::new(1, 1));
+        assert_eq!(maze.loop_length(), 8);
+        // The stray pipes around the loop are in the graph but never reached
+        assert_eq!(maze.farthest(), 4);
+        assert_eq!(
+            maze.loop_iter().take(3).collect::<Vec<IVec2>>(),
+            [IVec2::new(1, 1), IVec2::new(1, 2), IVec2::new(1, 3)]
+        );
+        assert_eq!(maze.enclosed_tiles(AreaMethod::Scan), 1);
+        assert_eq!(maze.enclosed_tiles(AreaMethod::Shoelace), 1);
+
+        let (_, grid) = parse(Span::new("...\n.S.\n...")).unwrap();
+        assert_eq!(PipeMaze::new(grid).unwrap_err(), MazeError::StartConnections(vec![]));
+    }
+
+    #[test]
+    fn parser_round_trip() {
+        let mut rng = Rng::new(10);
+        for _ in 0..200 {
+            let input = random_grid(&mut rng);
+            let (_, grid) = parse(Span::new(&input)).unwrap();
+            let width = input.lines().next().unwrap().len() as i32;
+            let printed = (0..input.lines().count() as i32)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| {
+                            let pipe_type = grid[&IVec2::new(x, y)];
+                            TILES.iter().find(|tile| tile.1 == pipe_type).unwrap().0
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            assert_eq!(printed, input);
+            assert_eq!(parse(Span::new(&printed)).unwrap().1, grid);
+        }
+    }
+
+    #[test]
+    fn mutated_grids_fail_cleanly() {
+        let mut rng = Rng::new(10);
+        for _ in 0..200 {
+            let input = random_grid(&mut rng);
+            for mutated in mutations(&mut rng, &input) {
+                if let Ok((_, grid)) = parse(Span::new(&mutated)) {
+                    let _ = PipeMaze::new(grid);
+                }
+                // Spaces are skipped like line breaks, any other glyph is unknown
+                if mutated.contains(|c: char| !"|-LJ7FS. \n".contains(c)) {
+                    assert!(all_consuming(parse)(Span::new(&mutated)).is_err(), "{mutated}");
+                }
+            }
+
+            let broken = replace_one(&mut rng, &input, |_, _| true, "#").unwrap();
+            assert!(all_consuming(parse)(Span::new(&broken)).is_err(), "{broken}");
+        }
+    }
 }
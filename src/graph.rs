@@ -0,0 +1,435 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::fmt::{Display, Write};
+use std::hash::Hash;
+
+/// A directed graph with labelled edges. Nodes are interned, so however a
+/// puzzle names them they are dense indices from here on.
+#[derive(Debug, Clone)]
+pub struct Graph<N, E> {
+    nodes: Vec<N>,
+    index: HashMap<N, usize>,
+    edges: Vec<Vec<(usize, E)>>,
+}
+
+impl<N: Clone + Eq + Hash, E> Graph<N, E> {
+    pub fn new() -> Self {
+        Graph { nodes: vec![], index: HashMap::new(), edges: vec![] }
+    }
+
+    /// Index of `node`, adding it first if it is new.
+    pub fn add_node(&mut self, node: N) -> usize {
+        if let Some(index) = self.index.get(&node) {
+            return *index;
+        }
+        self.nodes.push(node.clone());
+        self.edges.push(vec![]);
+        self.index.insert(node, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    pub fn add_edge(&mut self, from: N, to: N, label: E) {
+        let from = self.add_node(from);
+        let to = self.add_node(to);
+        self.edges[from].push((to, label));
+    }
+
+    pub fn index_of(&self, node: &N) -> Option<usize> {
+        self.index.get(node).copied()
+    }
+
+    pub fn node(&self, index: usize) -> &N {
+        &self.nodes[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn edges(&self, index: usize) -> &[(usize, E)] {
+        &self.edges[index]
+    }
+}
+
+// Not every search has a puzzle using it yet; the unused ones are kept as
+// part of the module's API and allowed as dead code individually.
+impl<N, E> Graph<N, E> {
+    /// Number of edges needed to reach every node from `start`, `None` for
+    /// unreachable nodes.
+    pub fn bfs(&self, start: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.edges.len()];
+        distances[start] = Some(0);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            let distance = distances[node].expect("queued nodes have a distance");
+            for (next, _) in &self.edges[node] {
+                if distances[*next].is_none() {
+                    distances[*next] = Some(distance + 1);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Nodes reachable from `start` in depth-first preorder.
+    #[allow(dead_code)]
+    pub fn dfs(&self, start: usize) -> Vec<usize> {
+        let mut visited = vec![false; self.edges.len()];
+        let mut order = vec![];
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            if visited[node] {
+                continue;
+            }
+            visited[node] = true;
+            order.push(node);
+            // Reversed, so the first edge is explored first
+            stack.extend(self.edges[node].iter().rev().map(|(next, _)| *next));
+        }
+        order
+    }
+
+    /// Cheapest cost to reach every node from `start`, with `cost` giving the
+    /// cost of an edge from its source, label and target.
+    #[allow(dead_code)]
+    pub fn dijkstra(&self, start: usize, cost: impl Fn(usize, &E, usize) -> u64) -> Vec<Option<u64>> {
+        let mut costs = vec![None; self.edges.len()];
+        let mut queue = BinaryHeap::from([Reverse((0, start))]);
+        while let Some(Reverse((total, node))) = queue.pop() {
+            if costs[node].is_some() {
+                continue;
+            }
+            costs[node] = Some(total);
+            for (next, label) in &self.edges[node] {
+                if costs[*next].is_none() {
+                    queue.push(Reverse((total + cost(node, label, *next), *next)));
+                }
+            }
+        }
+        costs
+    }
+
+    /// Cheapest path from `start` to `goal` and its cost. `heuristic` must
+    /// never overestimate the remaining cost, or the path may not be the
+    /// cheapest.
+    #[allow(dead_code)]
+    pub fn a_star(
+        &self,
+        start: usize,
+        goal: usize,
+        cost: impl Fn(usize, &E, usize) -> u64,
+        heuristic: impl Fn(usize) -> u64,
+    ) -> Option<(u64, Vec<usize>)> {
+        let mut best = vec![u64::MAX; self.edges.len()];
+        let mut previous = vec![None; self.edges.len()];
+        best[start] = 0;
+        let mut queue = BinaryHeap::from([Reverse((heuristic(start), start))]);
+        while let Some(Reverse((_, node))) = queue.pop() {
+            if node == goal {
+                let mut path = vec![goal];
+                while let Some(parent) = previous[*path.last().expect("path starts at the goal")] {
+                    path.push(parent);
+                }
+                path.reverse();
+                return Some((best[goal], path));
+            }
+            for (next, label) in &self.edges[node] {
+                let total = best[node] + cost(node, label, *next);
+                if total < best[*next] {
+                    best[*next] = total;
+                    previous[*next] = Some(node);
+                    queue.push(Reverse((total + heuristic(*next), *next)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Every node ordered so that edges only point forwards, or `None` when
+    /// the graph has a cycle.
+    #[allow(dead_code)]
+    pub fn topological_sort(&self) -> Option<Vec<usize>> {
+        let mut incoming = vec![0; self.edges.len()];
+        for (next, _) in self.edges.iter().flatten() {
+            incoming[*next] += 1;
+        }
+        let mut queue = (0..self.edges.len())
+            .filter(|node| incoming[*node] == 0)
+            .collect::<VecDeque<usize>>();
+        let mut order = vec![];
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for (next, _) in &self.edges[node] {
+                incoming[*next] -= 1;
+                if incoming[*next] == 0 {
+                    queue.push_back(*next);
+                }
+            }
+        }
+        (order.len() == self.edges.len()).then_some(order)
+    }
+
+    /// Strongly connected components with Tarjan's algorithm, run without
+    /// recursion so large grids don't overflow the stack. Components come
+    /// out in reverse topological order.
+    #[allow(dead_code)]
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        let count = self.edges.len();
+        let mut index = vec![None; count];
+        let mut low_link = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = vec![];
+        let mut components = vec![];
+        let mut next_index = 0;
+
+        for root in 0..count {
+            if index[root].is_some() {
+                continue;
+            }
+            // (node, position of the next edge to look at)
+            let mut work = vec![(root, 0)];
+            while let Some((node, edge)) = work.pop() {
+                if edge == 0 {
+                    index[node] = Some(next_index);
+                    low_link[node] = next_index;
+                    next_index += 1;
+                    stack.push(node);
+                    on_stack[node] = true;
+                }
+
+                if let Some((next, _)) = self.edges[node].get(edge) {
+                    work.push((node, edge + 1));
+                    match index[*next] {
+                        None => work.push((*next, 0)),
+                        Some(visited) if on_stack[*next] => {
+                            low_link[node] = low_link[node].min(visited);
+                        }
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                if Some(low_link[node]) == index[node] {
+                    let mut component = vec![];
+                    loop {
+                        let member = stack.pop().expect("the root is still on the stack");
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+                if let Some((parent, _)) = work.last() {
+                    low_link[*parent] = low_link[*parent].min(low_link[node]);
+                }
+            }
+        }
+        components
+    }
+}
+
+impl<N: Clone + Eq + Hash, E> Default for Graph<N, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How [`Graph::to_dot`] draws a graph.
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    /// Extra DOT attributes per node index, e.g. `color=red`.
+    pub node_attributes: BTreeMap<usize, String>,
+    /// Extra DOT attributes per `(from, to)` edge.
+    pub edge_attributes: BTreeMap<(usize, usize), String>,
+    /// Only draw this many nodes. Nodes reachable from `roots` go first.
+    pub max_nodes: Option<usize>,
+    pub roots: Vec<usize>,
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl<N: Clone + Eq + Hash + Display, E: Display> Graph<N, E> {
+    /// Renders the graph in Graphviz DOT format.
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        // Breadth first from the roots, so a capped drawing keeps the
+        // neighbourhood of the interesting nodes
+        let mut order = vec![];
+        let mut included = vec![false; self.len()];
+        let mut queue = options.roots.iter().copied().collect::<VecDeque<usize>>();
+        for root in &options.roots {
+            included[*root] = true;
+        }
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for (next, _) in self.edges(node) {
+                if !included[*next] {
+                    included[*next] = true;
+                    queue.push_back(*next);
+                }
+            }
+        }
+        order.extend((0..self.len()).filter(|node| !included[*node]));
+        order.truncate(options.max_nodes.unwrap_or(usize::MAX));
+
+        let mut drawn = vec![false; self.len()];
+        for node in &order {
+            drawn[*node] = true;
+        }
+
+        let mut dot = String::from("digraph {\n");
+        for node in &order {
+            let label = quote(&self.node(*node).to_string());
+            match options.node_attributes.get(node) {
+                Some(attributes) => writeln!(dot, "    {label} [{attributes}];"),
+                None => writeln!(dot, "    {label};"),
+            }
+            .expect("writing to a String can't fail");
+        }
+        for from in &order {
+            for (to, edge_label) in self.edges(*from).iter().filter(|(to, _)| drawn[*to]) {
+                let mut attributes = format!("label={}", quote(&edge_label.to_string()));
+                if let Some(extra) = options.edge_attributes.get(&(*from, *to)) {
+                    attributes = format!("{attributes}, {extra}");
+                }
+                writeln!(
+                    dot,
+                    "    {} -> {} [{attributes}];",
+                    quote(&self.node(*from).to_string()),
+                    quote(&self.node(*to).to_string()),
+                )
+                .expect("writing to a String can't fail");
+            }
+        }
+        if order.len() < self.len() {
+            writeln!(
+                dot,
+                "    label={};",
+                quote(&format!("{} of {} nodes shown", order.len(), self.len()))
+            )
+            .expect("writing to a String can't fail");
+        }
+        dot.push('}');
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 1);
+        graph.add_edge("b", "a", 2);
+        graph.add_edge("a", "c", 3);
+
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.index_of(&"c"), Some(2));
+        assert_eq!(graph.index_of(&"d"), None);
+        assert_eq!(graph.edges(0), &[(1, 1), (2, 3)]);
+        assert_eq!(graph.node(1), &"b");
+    }
+
+    #[test]
+    fn dot_output() {
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 'L');
+        graph.add_edge("b", "c\"", 'R');
+        graph.add_node("d");
+
+        let options = DotOptions {
+            node_attributes: BTreeMap::from([(0, "color=green".to_string())]),
+            edge_attributes: BTreeMap::from([((1, 2), "color=red".to_string())]),
+            ..DotOptions::default()
+        };
+        assert_eq!(
+            graph.to_dot(&options),
+            r#"digraph {
+    "a" [color=green];
+    "b";
+    "c\"";
+    "d";
+    "a" -> "b" [label="L"];
+    "b" -> "c\"" [label="R", color=red];
+}"#
+        );
+
+        let options = DotOptions { max_nodes: Some(2), roots: vec![3, 1], ..DotOptions::default() };
+        assert_eq!(
+            graph.to_dot(&options),
+            r#"digraph {
+    "d";
+    "b";
+    label="2 of 4 nodes shown";
+}"#
+        );
+    }
+
+    fn sample() -> Graph<&'static str, u64> {
+        let mut graph = Graph::new();
+        for (from, to, cost) in [
+            ("a", "b", 4),
+            ("a", "c", 1),
+            ("c", "b", 2),
+            ("b", "d", 5),
+            ("c", "d", 8),
+            ("d", "e", 3),
+        ] {
+            graph.add_edge(from, to, cost);
+        }
+        graph.add_node("f");
+        graph
+    }
+
+    #[test]
+    fn searches() {
+        let graph = sample();
+        let [a, b, c, d, e, f] = ["a", "b", "c", "d", "e", "f"].map(|node| graph.index_of(&node).unwrap());
+
+        let distances = graph.bfs(a);
+        assert_eq!(distances[d], Some(2));
+        assert_eq!(distances[e], Some(3));
+        assert_eq!(distances[f], None);
+        assert_eq!(graph.dfs(a), vec![a, b, d, e, c]);
+
+        let costs = graph.dijkstra(a, |_, cost, _| *cost);
+        assert_eq!(costs[b], Some(3));
+        assert_eq!(costs[e], Some(11));
+        assert_eq!(costs[f], None);
+
+        assert_eq!(graph.a_star(a, e, |_, cost, _| *cost, |_| 0), Some((11, vec![a, c, b, d, e])));
+        assert_eq!(graph.a_star(a, f, |_, cost, _| *cost, |_| 0), None);
+    }
+
+    #[test]
+    fn orderings() {
+        let mut graph = sample();
+        let order = graph.topological_sort().unwrap();
+        for from in 0..graph.len() {
+            for (to, _) in graph.edges(from) {
+                let position = |node| order.iter().position(|n| *n == node).unwrap();
+                assert!(position(from) < position(*to));
+            }
+        }
+        assert_eq!(graph.strongly_connected_components().len(), graph.len());
+
+        graph.add_edge("e", "c", 1);
+        assert_eq!(graph.topological_sort(), None);
+        let mut components = graph
+            .strongly_connected_components()
+            .into_iter()
+            .map(|mut component| {
+                component.sort();
+                component.into_iter().map(|node| *graph.node(node)).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        components.sort();
+        assert_eq!(components, vec![vec!["a"], vec!["b", "c", "d", "e"], vec!["f"]]);
+    }
+}
@@ -2,10 +2,30 @@
 
 mod problem;
 mod days;
+mod graph;
+mod math;
+mod matcher;
+#[cfg(test)]
+mod testing;
 
 use problem::Problem;
 use days::*;
 
+const USAGE: &str = "usage: advent-of-code <day> [1|2]
+       advent-of-code 1 --explain [--part 1|2] [--skip-missing]
+       advent-of-code 2 possible [--bag \"12 red, 13 green, 14 blue\"]
+       advent-of-code 2 stats [--bag \"12 red, 13 green, 14 blue\"]
+       advent-of-code 3 report
+       advent-of-code 3 total [--symbols CHARS] [--count K] [--sum]
+       advent-of-code 4 cascade
+       advent-of-code 4 check [--lenient]
+       advent-of-code 5 lowest [--forward]
+       advent-of-code 7 report [--part 1|2] [--suits CHARS] [--json]
+       advent-of-code 8 dot [--max-nodes N]
+       advent-of-code 9 triangle [--line N]
+       advent-of-code 10 enclosed [--shoelace]
+       advent-of-code 10 render [--ansi]";
+
 enum Days {
     Day1,
     Day2,
@@ -19,7 +39,23 @@ enum Days {
     Day10,
 }
 
-fn day_to_problem(day: Days) -> Option<Box<dyn Problem>> {
+fn parse_day(day: &str) -> Option<Days> {
+    match day.trim_start_matches("day").parse::<u8>().ok()? {
+        1 => Some(Days::Day1),
+        2 => Some(Days::Day2),
+        3 => Some(Days::Day3),
+        4 => Some(Days::Day4),
+        5 => Some(Days::Day5),
+        6 => Some(Days::Day6),
+        7 => Some(Days::Day7),
+        8 => Some(Days::Day8),
+        9 => Some(Days::Day9),
+        10 => Some(Days::Day10),
+        _ => None,
+    }
+}
+
+fn day_to_problem(day: &Days) -> Option<Box<dyn Problem>> {
     match day {
         Days::Day1 => Some(Box::new(day01::day_one::DayOne{})),
         Days::Day2 => Some(Box::new(day02::day_two::DayTwo{})),
@@ -34,10 +70,163 @@ fn day_to_problem(day: Days) -> Option<Box<dyn Problem>> {
     }
 }
 
+fn day_to_input(day: &Days) -> &'static str {
+    match day {
+        Days::Day1 => include_str!("../src/days/day01/input.txt"),
+        Days::Day2 => include_str!("../src/days/day02/input.txt"),
+        Days::Day3 => include_str!("../src/days/day03/input.txt"),
+        Days::Day4 => include_str!("../src/days/day04/input.txt"),
+        Days::Day5 => include_str!("../src/days/day05/input.txt"),
+        Days::Day6 => include_str!("../src/days/day06/input.txt"),
+        Days::Day7 => include_str!("../src/days/day07/input.txt"),
+        Days::Day8 => include_str!("../src/days/day08/input.txt"),
+        Days::Day9 => include_str!("../src/days/day09/input.txt"),
+        Days::Day10 => include_str!("../src/days/day10/input.txt"),
+    }
+}
+
+/// The `--name` / `--name value` options following a command.
+struct Flags<'a>(&'a [String]);
+
+impl<'a> Flags<'a> {
+    fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|arg| arg == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&'a str> {
+        self.0
+            .iter()
+            .position(|arg| arg == name)
+            .and_then(|index| self.0.get(index + 1))
+            .map(String::as_str)
+    }
+}
+
+/// The `--bag` Day 2 games are checked against, or the standard one. A bag
+/// that doesn't parse ends the program with its error.
+fn day2_bag(flags: &Flags) -> day02::day_two::Bag {
+    match flags.value("--bag").map(day02::day_two::Bag::parse) {
+        Some(Ok(bag)) => bag,
+        Some(Err(e)) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+        None => day02::day_two::Bag::standard(),
+    }
+}
+
+/// Runs one of the debugging commands a day offers next to its two parts.
+fn run_command(day: &Days, command: &str, flags: Flags, input: &str) -> Option<String> {
+    match (day, command) {
+        (Days::Day1, "--explain") => {
+            let part = flags.value("--part").map_or(Some(2), |part| part.parse().ok())?;
+            let missing = if flags.has("--skip-missing") {
+                day01::day_one::MissingDigits::Skip
+            } else {
+                day01::day_one::MissingDigits::Fail
+            };
+            day01::day_one::explain(input, part, missing)
+        }
+        (Days::Day2, "possible") => Some(day02::day_two::possible_games(input, &day2_bag(&flags))),
+        (Days::Day2, "stats") => Some(day02::day_two::stats(input, &day2_bag(&flags))),
+        (Days::Day3, "report") => Some(day03::day_three::report(input)),
+        (Days::Day3, "total") => {
+            let count = flags.value("--count").map_or(Some(2), |count| count.parse().ok())?;
+            let combine = if flags.has("--sum") {
+                day03::day_three::Combine::Sum
+            } else {
+                day03::day_three::Combine::Product
+            };
+            let symbols = flags.value("--symbols").unwrap_or("*");
+            Some(day03::day_three::symbol_total(input, symbols, count, combine))
+        }
+        (Days::Day4, "cascade") => Some(day04::day_four::trace(input)),
+        (Days::Day4, "check") => {
+            let validation = if flags.has("--lenient") {
+                day04::day_four::Validation::Lenient
+            } else {
+                day04::day_four::Validation::Strict
+            };
+            Some(day04::day_four::check(input, validation))
+        }
+        (Days::Day5, "lowest") => {
+            let strategy = if flags.has("--forward") {
+                day05::day_five::Strategy::Forward
+            } else {
+                day05::day_five::Strategy::Backward
+            };
+            Some(day05::day_five::lowest(input, strategy))
+        }
+        (Days::Day7, "report") => {
+            let part = flags.value("--part").map_or(Some(2), |part| part.parse().ok())?;
+            let format = if flags.has("--json") {
+                day07::day_seven::ReportFormat::Json
+            } else {
+                day07::day_seven::ReportFormat::Table
+            };
+            day07::day_seven::report(input, part, flags.value("--suits"), format)
+        }
+        (Days::Day8, "dot") => {
+            let max_nodes = match flags.value("--max-nodes") {
+                Some(max_nodes) => Some(max_nodes.parse().ok()?),
+                None => None,
+            };
+            Some(day08::day_eight::to_dot(input, max_nodes))
+        }
+        (Days::Day9, "triangle") => {
+            let line = match flags.value("--line") {
+                Some(line) => Some(line.parse().ok()?),
+                None => None,
+            };
+            let Some(explanation) = day09::day_nine::explain(input, line) else {
+                eprintln!("error: unknown line {}", flags.value("--line").unwrap_or_default());
+                std::process::exit(2);
+            };
+            Some(explanation)
+        }
+        (Days::Day10, "enclosed") => {
+            let method = if flags.has("--shoelace") {
+                day10::day_ten::AreaMethod::Shoelace
+            } else {
+                day10::day_ten::AreaMethod::Scan
+            };
+            Some(day10::day_ten::enclosed(input, method))
+        }
+        (Days::Day10, "render") => {
+            let style = if flags.has("--ansi") {
+                day10::day_ten::RenderStyle::Ansi
+            } else {
+                day10::day_ten::RenderStyle::Plain
+            };
+            Some(day10::day_ten::render(input, style))
+        }
+        _ => None,
+    }
+}
+
 fn main() {
-    let problem = day_to_problem(Days::Day9);
-    match problem {
-        Some(problem) => println!("{}", problem.part_two(include_str!("../src/days/day09/input.txt"))),
-        None => println!("Not yet implemented")
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let Some(day) = args.first().and_then(|day| parse_day(day)) else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+    let input = day_to_input(&day);
+
+    let output = match (day_to_problem(&day), args.get(1).map(String::as_str)) {
+        (Some(problem), None) => {
+            Some(format!("{}\n{}", problem.part_one(input), problem.part_two(input)))
+        }
+        (Some(problem), Some("1")) => Some(problem.part_one(input)),
+        (Some(problem), Some("2")) => Some(problem.part_two(input)),
+        (_, Some(command)) => run_command(&day, command, Flags(&args[2..]), input),
+        (None, None) => None,
+    };
+
+    match output {
+        Some(output) => println!("{output}"),
+        None => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
     }
 }
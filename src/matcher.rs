@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// An occurrence of one of a [`TokenMatcher`]'s tokens.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match<'m, V> {
+    /// Byte offset of the token's first byte.
+    pub start: usize,
+    pub token: &'m str,
+    pub value: &'m V,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: BTreeMap<u8, usize>,
+    /// The node for the longest proper suffix of this node's path that is
+    /// also a path in the trie.
+    fail: usize,
+    /// Tokens ending at this node, including those reached through `fail`.
+    outputs: Vec<usize>,
+}
+
+/// Finds every occurrence of a set of tokens in a single pass over the input,
+/// using an Aho-Corasick automaton. Overlapping occurrences are all reported,
+/// so `"twone"` matches both `"two"` and `"one"`.
+#[derive(Debug)]
+pub struct TokenMatcher<V> {
+    nodes: Vec<Node>,
+    tokens: Vec<(String, V)>,
+}
+
+impl<V> TokenMatcher<V> {
+    pub fn new<S: Into<String>>(tokens: impl IntoIterator<Item = (S, V)>) -> Self {
+        let tokens = tokens
+            .into_iter()
+            .map(|(token, value)| (token.into(), value))
+            .collect::<Vec<(String, V)>>();
+
+        let mut nodes = vec![Node::default()];
+        for (index, (token, _)) in tokens.iter().enumerate() {
+            let mut current = 0;
+            for byte in token.bytes() {
+                current = match nodes[current].children.get(&byte) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(Node::default());
+                        let child = nodes.len() - 1;
+                        nodes[current].children.insert(byte, child);
+                        child
+                    }
+                };
+            }
+            nodes[current].outputs.push(index);
+        }
+
+        // Breadth first, so every fail link points at a node that's already done
+        let mut queue = nodes[0].children.values().copied().collect::<VecDeque<usize>>();
+        while let Some(node) = queue.pop_front() {
+            let children = nodes[node]
+                .children
+                .iter()
+                .map(|(&byte, &child)| (byte, child))
+                .collect::<Vec<(u8, usize)>>();
+            for (byte, child) in children {
+                let mut fallback = nodes[node].fail;
+                let fail = loop {
+                    match nodes[fallback].children.get(&byte) {
+                        Some(&next) => break next,
+                        None if fallback == 0 => break 0,
+                        None => fallback = nodes[fallback].fail,
+                    }
+                };
+                nodes[child].fail = fail;
+                let inherited = nodes[fail].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        TokenMatcher { nodes, tokens }
+    }
+
+    fn step(&self, mut node: usize, byte: u8) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[node].children.get(&byte) {
+                return next;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = self.nodes[node].fail;
+        }
+    }
+
+    /// Every occurrence of a token in `haystack`, ordered by where it ends.
+    /// Occurrences ending at the same byte come longest first.
+    pub fn find_iter<'m, 'h>(&'m self, haystack: &'h str) -> impl Iterator<Item = Match<'m, V>> + 'h
+    where
+        'm: 'h,
+    {
+        haystack
+            .bytes()
+            .enumerate()
+            .scan(0, move |node, (index, byte)| {
+                *node = self.step(*node, byte);
+                Some((index, *node))
+            })
+            .flat_map(move |(index, node)| {
+                self.nodes[node].outputs.iter().map(move |&token| {
+                    let (token, value) = &self.tokens[token];
+                    Match { start: index + 1 - token.len(), token, value }
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_tokens() {
+        let matcher = TokenMatcher::new([("he", 1), ("she", 2), ("his", 3), ("hers", 4)]);
+        let found = matcher
+            .find_iter("ushers")
+            .map(|found| (found.start, found.token))
+            .collect::<Vec<(usize, &str)>>();
+        assert_eq!(found, [(1, "she"), (2, "he"), (2, "hers")]);
+
+        let matcher = TokenMatcher::new([("two", 2), ("one", 1), ("eight", 8)]);
+        let values = matcher
+            .find_iter("eightwone")
+            .map(|found| *found.value)
+            .collect::<Vec<u32>>();
+        assert_eq!(values, [8, 2, 1]);
+    }
+
+    #[test]
+    fn brute_force_agrees() {
+        let tokens = ["a", "ab", "bab", "bc", "bca", "c", "caa"];
+        let matcher = TokenMatcher::new(tokens.iter().map(|token| (*token, ())));
+        let haystack = "abccab bcaabcab caaab";
+
+        let mut found = matcher
+            .find_iter(haystack)
+            .map(|found| (found.start, found.token))
+            .collect::<Vec<(usize, &str)>>();
+        found.sort();
+        let mut expected = (0..haystack.len())
+            .flat_map(|start| {
+                tokens
+                    .iter()
+                    .filter(move |token| haystack[start..].starts_with(**token))
+                    .map(move |token| (start, *token))
+            })
+            .collect::<Vec<(usize, &str)>>();
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,150 @@
+/// Largest integer whose square doesn't exceed `n`.
+pub fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+
+    // Newton's method from an overestimate only ever decreases
+    let mut x = 1u128 << ((128 - n.leading_zeros()) / 2 + 1);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Number of hold times `speed` in `0..=time` for which
+/// `speed * (time - speed) > record`.
+///
+/// The winning hold times lie strictly between the roots of
+/// `speed² - time·speed + record = 0`, which are found with an integer square
+/// root and nudged onto the first hold time that actually beats the record.
+/// A hold time landing exactly on a root ties the record and doesn't count.
+pub fn winning_hold_times(time: u64, record: u64) -> u64 {
+    let (time, record) = (time as u128, record as u128);
+    let beats = |speed: u128| speed * (time - speed) > record;
+
+    let discriminant = match (time * time).checked_sub(4 * record) {
+        Some(discriminant) if discriminant > 0 => discriminant,
+        _ => return 0,
+    };
+
+    let mut low = (time - isqrt(discriminant).min(time)) / 2;
+    while low > 0 && beats(low - 1) {
+        low -= 1;
+    }
+    while low <= time / 2 && !beats(low) {
+        low += 1;
+    }
+    if low > time / 2 {
+        return 0;
+    }
+
+    // The distance is symmetric around time / 2
+    let high = time - low;
+    (high - low + 1) as u64
+}
+
+pub fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        return a
+    }
+    gcd(b, a % b)
+}
+
+/// Inverse of `a` modulo `m`, for coprime `a` and `m`. `None` when `m` is too
+/// large for the signed arithmetic.
+fn mod_inverse(a: u128, m: u128) -> Option<u128> {
+    let m = i128::try_from(m).ok()?;
+    let (mut old_r, mut r) = (i128::try_from(a).ok()?, m);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    Some(old_s.rem_euclid(m) as u128)
+}
+
+/// Combines `x ≡ a1 (mod m1)` and `x ≡ a2 (mod m2)` into a single
+/// `x ≡ a (mod lcm(m1, m2))`, returned as `(a, lcm)`. The moduli don't have
+/// to be coprime; `None` means the two congruences contradict each other, or
+/// that the combined modulus or a product on the way doesn't fit in a `u128`.
+pub fn crt(a1: u128, m1: u128, a2: u128, m2: u128) -> Option<(u128, u128)> {
+    let (a1, a2) = (a1 % m1, a2 % m2);
+    let g = gcd(m1, m2);
+    // a2 - a1 modulo m2, without going through a2 + m2
+    let difference = match a1 % m2 {
+        shift if shift <= a2 => a2 - shift,
+        shift => m2 - (shift - a2),
+    };
+    if !difference.is_multiple_of(g) {
+        return None;
+    }
+
+    let lcm = (m1 / g).checked_mul(m2)?;
+    let reduced = m2 / g;
+    let inverse = mod_inverse(m1 / g % reduced, reduced)?;
+    let k = ((difference / g) % reduced).checked_mul(inverse)? % reduced;
+    Some((m1.checked_mul(k)?.checked_add(a1)? % lcm, lcm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_square_root() {
+        for n in 0..10_000u128 {
+            let root = isqrt(n);
+            assert!(root * root <= n && (root + 1) * (root + 1) > n, "isqrt({n}) = {root}");
+        }
+        assert_eq!(isqrt(u64::MAX as u128 * u64::MAX as u128), u64::MAX as u128);
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+    }
+
+    #[test]
+    fn ties_at_the_roots() {
+        // 2 * 8 and 8 * 2 equal the record exactly
+        assert_eq!(winning_hold_times(10, 16), 5);
+        // 5 * 5 is the best possible distance
+        assert_eq!(winning_hold_times(10, 25), 0);
+        assert_eq!(winning_hold_times(10, 24), 1);
+        assert_eq!(winning_hold_times(0, 0), 0);
+        assert_eq!(winning_hold_times(1, 0), 0);
+        assert_eq!(winning_hold_times(2, 0), 1);
+    }
+
+    #[test]
+    fn chinese_remainder() {
+        assert_eq!(crt(2, 3, 3, 5), Some((8, 15)));
+        assert_eq!(crt(0, 1, 4, 7), Some((4, 7)));
+        // Non-coprime moduli
+        assert_eq!(crt(2, 4, 4, 6), Some((10, 12)));
+        assert_eq!(crt(1, 4, 2, 6), None);
+
+        // Coprime moduli whose product only just fits, then overflows
+        let (m1, m2) = (u64::MAX as u128, 1u128 << 64);
+        let (a, lcm) = crt(5, m1, 7, m2).unwrap();
+        assert_eq!((lcm, a % m1, a % m2), (m1 * m2, 5, 7));
+        assert_eq!(crt(5, m1, 7, (1 << 64) + 3), None);
+        assert_eq!(crt(3, u128::MAX, 1, 2), None);
+
+        for m1 in 1..20u128 {
+            for m2 in 1..20u128 {
+                for a1 in 0..m1 {
+                    for a2 in 0..m2 {
+                        let brute = (0..m1 * m2).find(|x| x % m1 == a1 && x % m2 == a2);
+                        let solved = crt(a1, m1, a2, m2);
+                        assert_eq!(solved.map(|(a, _)| a), brute, "{a1} mod {m1}, {a2} mod {m2}");
+                        if let Some((_, lcm)) = solved {
+                            assert_eq!(lcm, m1 * m2 / gcd(m1, m2));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
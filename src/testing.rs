@@ -0,0 +1,74 @@
+//! Helpers for the randomised tests.
+
+/// A xorshift generator, so randomised tests see the same values on every
+/// run.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // Zero is xorshift's one fixed point
+        Rng(seed.max(1))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A value in `0..bound`.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// A length or index in `low..high`.
+    pub(crate) fn between(&mut self, low: usize, high: usize) -> usize {
+        low + self.below((high - low) as u64) as usize
+    }
+
+    pub(crate) fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// `input` with one of the characters `eligible` accepts, picked at random,
+/// replaced by `with`. Returns `None` when no character is eligible.
+pub(crate) fn replace_one(
+    rng: &mut Rng,
+    input: &str,
+    eligible: impl Fn(usize, char) -> bool,
+    with: &str,
+) -> Option<String> {
+    let candidates = input
+        .char_indices()
+        .filter(|(index, character)| eligible(*index, *character))
+        .map(|(index, character)| (index, character.len_utf8()))
+        .collect::<Vec<(usize, usize)>>();
+    if candidates.is_empty() {
+        return None;
+    }
+    let (index, length) = *rng.pick(&candidates);
+    Some(format!("{}{with}{}", &input[..index], &input[index + length..]))
+}
+
+/// Copies of `input` cut short or with a character swapped for another
+/// printable one. A parser may accept some of them, but mustn't panic.
+pub(crate) fn mutations(rng: &mut Rng, input: &str) -> Vec<String> {
+    let boundaries = input
+        .char_indices()
+        .map(|(index, _)| index)
+        .collect::<Vec<usize>>();
+    let mut mutated = vec![];
+    if boundaries.is_empty() {
+        return mutated;
+    }
+    for _ in 0..4 {
+        let cut = *rng.pick(&boundaries);
+        mutated.push(input[..cut].to_string());
+
+        let character = char::from(b' ' + rng.below(95) as u8).to_string();
+        mutated.extend(replace_one(rng, input, |_, _| true, &character));
+    }
+    mutated
+}